@@ -0,0 +1,282 @@
+/*
+    Command line
+
+    cheng                                   UCI mode
+    cheng uci                               UCI mode
+    cheng bench [depth]                     bench signature
+    cheng perft <depth> [fen]               move generation node counts
+    cheng search [--fen FEN] [--depth D | --movetime MS]
+    cheng eval [--fen FEN]                  static evaluation breakdown
+    cheng epd <file> [--depth D | --movetime MS]
+    cheng tune <dataset> <output> [--iterations N]
+    cheng makebook <pgn> <output> [--plies N]
+    cheng match <engine1> <engine2> [--games N] [--openings FILE] [--tc BASE+INC]
+          [--movetime MS] [--nodes N] [--depth D] [--pgn FILE]
+          [--sprt ELO0 ELO1] [--alpha A] [--beta B]
+    cheng --version | --help
+
+    Subcommands run once & exit, searches don't listen to stdin so the
+    binary can be driven from scripts with a closed or redirected input.
+    Errors print the usage & exit with code 2.
+*/
+
+use super::*;
+
+// exit codes
+const EXIT_OK: i32 = 0;
+const EXIT_USAGE: i32 = 2;
+
+// default search depth when neither a depth nor a time is given
+const SEARCH_DEPTH: usize = 10;
+
+static USAGE: &str = "usage: cheng [command]
+
+commands:
+    (none) | uci                                    run in UCI mode
+    bench [depth]                                   search the bench positions & print the node signature
+    perft <depth> [fen]                             count leaf nodes of the move generator
+    search [--fen FEN] [--depth D | --movetime MS]  search a position & print the best move
+    eval [--fen FEN]                                print the static evaluation of a position
+    epd <file> [--depth D | --movetime MS]          run a test suite & print the solved count
+    tune <dataset> <output> [--iterations N]        tune the evaluation weights on a dataset of positions & results
+    makebook <pgn> <output> [--plies N]             build a Polyglot opening book from the games of a PGN file
+    match <engine1> <engine2> [--games N] [--openings FILE] [--tc BASE+INC] [--movetime MS]
+          [--nodes N] [--depth D] [--pgn FILE] [--sprt ELO0 ELO1] [--alpha A] [--beta B]
+                                                    play a match between two UCI engines
+
+options:
+    -h, --help                                      print this message
+    -V, --version                                   print the version";
+
+fn usage_error(message: &str) -> i32 {
+    eprintln!("error: {}", message);
+    eprintln!("{}", USAGE);
+
+    EXIT_USAGE
+}
+
+// value of an option, the fen may be given unquoted so it takes every word up to the next option
+fn option_value(args: &[String], name: &str) -> Option<String> {
+    let index = args.iter().position(|arg| arg == name)?;
+
+    let words = args[index + 1..].iter().take_while(|arg| !arg.starts_with("--")).map(|arg| arg.as_str()).collect::<Vec<&str>>();
+
+    if words.is_empty() {
+        None
+    }else {
+        Some(words.join(" "))
+    }
+}
+
+// options other than the given ones
+fn unknown_option<'a>(args: &'a [String], known: &[&str]) -> Option<&'a String> {
+    args.iter().find(|arg| arg.starts_with("--") && !known.contains(&arg.as_str()))
+}
+
+// set up the position given by --fen or the start position
+fn setup_position(args: &[String], char_pieces: &HashMap<char, u32>) -> Result<(), String> {
+    match option_value(args, "--fen") {
+        Some(fen) => try_parse_fen(&fen, char_pieces)?,
+        None if args.iter().any(|arg| arg == "--fen") => return Err(String::from("--fen needs a value")),
+        None => parse_fen(START_POSTITION, char_pieces),
+    }
+
+    Ok(())
+}
+
+fn parse_number(value: Option<String>, name: &str) -> Result<Option<usize>, String> {
+    match value {
+        Some(val) => match val.parse::<usize>() {
+            Ok(number) if number > 0 => Ok(Some(number)),
+            _ => Err(format!("invalid value for {}: {}", name, val)),
+        },
+        None => Ok(None),
+    }
+}
+
+fn perft(depth: usize, fen: &str, char_pieces: &HashMap<char, u32>) -> Result<(), String> {
+    try_parse_fen(fen, char_pieces)?;
+
+    let start = get_time_ms();
+
+    let nodes = perft_driver(depth as u64, true);
+
+    let elapsed = get_time_ms() - start;
+
+    println!();
+    println!("depth {} nodes {} time {} nps {}", depth, nodes, elapsed, nodes as u64 * 1000 / elapsed.max(1));
+
+    Ok(())
+}
+
+fn search(args: &[String], char_pieces: &HashMap<char, u32>, ht: &mut HashMap<u64, TTEntry>) -> Result<(), String> {
+    if let Some(arg) = unknown_option(args, &["--fen", "--depth", "--movetime"]) {
+        return Err(format!("unknown option for search: {}", arg));
+    }
+
+    setup_position(args, char_pieces)?;
+
+    let depth = parse_number(option_value(args, "--depth"), "--depth")?;
+    let movetime = parse_number(option_value(args, "--movetime"), "--movetime")?;
+
+    // the search is driven like a UCI "go" command
+    let command = match (depth, movetime) {
+        (Some(_), Some(_)) => return Err(String::from("give either --depth or --movetime")),
+        (Some(val), None) => format!("go depth {}", val),
+        (None, Some(val)) => format!("go movetime {}", val),
+        (None, None) => format!("go depth {}", SEARCH_DEPTH),
+    };
+
+    parse_go(command, ht);
+
+    Ok(())
+}
+
+fn epd_suite(args: &[String], char_pieces: &HashMap<char, u32>, ht: &mut HashMap<u64, TTEntry>) -> Result<(), String> {
+    if let Some(arg) = unknown_option(args, &["--depth", "--movetime"]) {
+        return Err(format!("unknown option for epd: {}", arg));
+    }
+
+    let path = match args.first() {
+        Some(path) if !path.starts_with("--") => path,
+        _ => return Err(String::from("epd needs a file")),
+    };
+
+    let depth = parse_number(option_value(args, "--depth"), "--depth")?;
+    let movetime = parse_number(option_value(args, "--movetime"), "--movetime")?;
+
+    let limit = match (depth, movetime) {
+        (Some(_), Some(_)) => return Err(String::from("give either --depth or --movetime")),
+        (Some(val), None) => epd::Limit::Depth(val),
+        (None, Some(val)) => epd::Limit::MoveTime(val as u64),
+        (None, None) => epd::Limit::MoveTime(epd::DEFAULT_MOVETIME),
+    };
+
+    epd::run_suite(path, limit, char_pieces, ht);
+
+    Ok(())
+}
+
+// files given before the options
+fn file_arguments(args: &[String], count: usize) -> Option<Vec<&String>> {
+    let files = args.iter().take_while(|arg| !arg.starts_with("--")).collect::<Vec<&String>>();
+
+    if files.len() == count { Some(files) } else { None }
+}
+
+fn tune_eval(args: &[String], char_pieces: &HashMap<char, u32>) -> Result<(), String> {
+    if let Some(arg) = unknown_option(args, &["--iterations"]) {
+        return Err(format!("unknown option for tune: {}", arg));
+    }
+
+    let files = file_arguments(args, 2).ok_or(String::from("tune needs a dataset & an output file"))?;
+
+    let iterations = parse_number(option_value(args, "--iterations"), "--iterations")?;
+
+    tune(files[0], files[1], iterations.unwrap_or(TUNE_ITERATIONS), char_pieces);
+
+    Ok(())
+}
+
+fn make_book(args: &[String], char_pieces: &HashMap<char, u32>) -> Result<(), String> {
+    if let Some(arg) = unknown_option(args, &["--plies"]) {
+        return Err(format!("unknown option for makebook: {}", arg));
+    }
+
+    let files = file_arguments(args, 2).ok_or(String::from("makebook needs a PGN file & an output file"))?;
+
+    let plies = parse_number(option_value(args, "--plies"), "--plies")?;
+
+    polyglot::make_book(files[0], files[1], plies.unwrap_or(polyglot::BOOK_PLIES), char_pieces);
+
+    Ok(())
+}
+
+fn play_match(args: &[String], char_pieces: &HashMap<char, u32>) -> Result<(), String> {
+    let known = ["--games", "--openings", "--tc", "--movetime", "--nodes", "--depth", "--pgn", "--sprt", "--alpha", "--beta"];
+
+    if let Some(arg) = unknown_option(args, &known) {
+        return Err(format!("unknown option for match: {}", arg));
+    }
+
+    if file_arguments(args, 2).is_none() {
+        return Err(String::from("match needs two engines"));
+    }
+
+    // same options as the UCI match command
+    let args = args.iter().map(|arg| arg.strip_prefix("--").unwrap_or(arg)).collect::<Vec<&str>>();
+
+    let settings = tournament::parse_settings(&args)?;
+
+    tournament::run_match(&settings, char_pieces);
+
+    Ok(())
+}
+
+fn eval(args: &[String], char_pieces: &HashMap<char, u32>) -> Result<(), String> {
+    if let Some(arg) = unknown_option(args, &["--fen"]) {
+        return Err(format!("unknown option for eval: {}", arg));
+    }
+
+    setup_position(args, char_pieces)?;
+
+    print_evaluation();
+
+    Ok(())
+}
+
+// run a subcommand & return the exit code
+pub fn run(args: &[String], char_pieces: &HashMap<char, u32>, ht: &mut HashMap<u64, TTEntry>) -> i32 {
+    unsafe {
+        LISTEN_INPUT = false;
+    }
+
+    let result = match args[0].as_str() {
+        "uci" => {
+            unsafe {
+                LISTEN_INPUT = true;
+            }
+            uci_loop(char_pieces, ht);
+            Ok(())
+        },
+        "bench" => {
+            match parse_number(args.get(1).cloned(), "depth") {
+                Ok(depth) => {
+                    bench::bench(depth.unwrap_or(bench::BENCH_DEPTH), char_pieces, ht);
+                    Ok(())
+                },
+                Err(e) => Err(e),
+            }
+        },
+        "perft" => {
+            match parse_number(args.get(1).cloned(), "depth") {
+                Ok(Some(depth)) => {
+                    let fen = if args.len() > 2 { args[2..].join(" ") } else { String::from(START_POSTITION) };
+                    perft(depth, &fen, char_pieces)
+                },
+                Ok(None) => Err(String::from("perft needs a depth")),
+                Err(e) => Err(e),
+            }
+        },
+        "search" => search(&args[1..], char_pieces, ht),
+        "eval" => eval(&args[1..], char_pieces),
+        "epd" => epd_suite(&args[1..], char_pieces, ht),
+        "tune" => tune_eval(&args[1..], char_pieces),
+        "makebook" => make_book(&args[1..], char_pieces),
+        "match" => play_match(&args[1..], char_pieces),
+        "-V" | "--version" => {
+            println!("cheng {}", env!("CARGO_PKG_VERSION"));
+            Ok(())
+        },
+        "-h" | "--help" => {
+            println!("{}", USAGE);
+            Ok(())
+        },
+        command => Err(format!("unknown command: {}", command)),
+    };
+
+    match result {
+        Ok(_) => EXIT_OK,
+        Err(e) => usage_error(&e),
+    }
+}
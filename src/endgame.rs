@@ -0,0 +1,578 @@
+/*
+    Endgame knowledge
+
+    Known endgames are recognised by their material signature (piece
+    counts of both sides). A specialised evaluator replaces the static
+    evaluation of endgames with a known outcome (KXK, KBNK, KQKR, KPK)
+    and a scale factor shrinks the endgame score of drawish material
+    (wrong bishop + rook pawn, opposite-coloured bishops).
+
+    Specialised scores stay well below MATE_SCORE so they never look
+    like mates to the search.
+*/
+
+use super::*;
+
+// base score of a won endgame
+const KNOWN_WIN: i32 = 10000;
+
+// scale factors of the endgame score (SCALE_NORMAL keeps it as is)
+pub const SCALE_NORMAL: i32 = 64;
+const SCALE_DRAW: i32 = 0;
+const SCALE_OPPOSITE_BISHOPS: i32 = 24;
+const SCALE_OPPOSITE_BISHOPS_WITH_PIECES: i32 = 48;
+
+// number of KPK positions [side to move][weak king][strong king][pawn on files a-d, ranks 2-7]
+const KPK_SIZE: usize = 2 * 64 * 64 * 24;
+
+// KPK positions won by the side with the pawn (white, pawn on files a-d)
+static mut KPK_BITBASE: [bool; KPK_SIZE] = [false; KPK_SIZE];
+
+// material signature of a position: 4 bits per piece count [piece]
+const fn signature(code: &str) -> u64 {
+    let bytes = code.as_bytes();
+    let mut key = 0;
+    let mut side = 0;
+    let mut index = 0;
+
+    while index < bytes.len() {
+        let piece = match bytes[index] {
+            b'K' => {
+                // second king starts the weak side
+                if index > 0 {
+                    side = 1;
+                }
+
+                index += 1;
+                continue;
+            },
+            b'P' => 0,
+            b'N' => 1,
+            b'B' => 2,
+            b'R' => 3,
+            b'Q' => 4,
+            _ => panic!("invalid material signature"),
+        };
+
+        key += 1 << ((side * 6 + piece) * 4);
+        index += 1;
+    }
+
+    key
+}
+
+// same material with colors swapped
+fn flip_signature(key: u64) -> u64 {
+    ((key & 0xFFFFFF) << 24) | (key >> 24)
+}
+
+// material signature of the current position (kings excluded)
+fn material_signature() -> u64 {
+    let mut key = 0;
+
+    for piece in Piece::P as usize..=Piece::k as usize {
+        if piece % 6 != Piece::K as usize {
+            key += (piece_count(piece) as u64) << (piece * 4);
+        }
+    }
+
+    key
+}
+
+fn piece_count(piece: usize) -> i32 {
+    unsafe { count_bits(PIECE_BITBOARDS[piece]) as i32 }
+}
+
+// evaluator given the color of the strong side, the score is for that side
+type EndgameEvaluator = fn(usize) -> i32;
+
+// specialised evaluators by signature with white as the strong side
+static ENDGAMES: [(u64, EndgameEvaluator); 3] = [
+    (signature("KBNK"), evaluate_kbnk),
+    (signature("KQKR"), evaluate_kqkr),
+    (signature("KPK"), evaluate_kpk),
+];
+
+// distance of a square from the board centre (0 in the centre, 6 in a corner)
+fn centre_distance(square: usize) -> i32 {
+    let file = (square % 8) as i32;
+    let rank = (square / 8) as i32;
+
+    (3 - file).max(file - 4) + (3 - rank).max(rank - 4)
+}
+
+// drive the weak king to the edge
+fn push_to_edge(square: usize) -> i32 {
+    20 * centre_distance(square)
+}
+
+// bring the kings together
+fn push_close(square_1: usize, square_2: usize) -> i32 {
+    10 * (7 - square_distance(square_1, square_2))
+}
+
+// material of a side (kings excluded)
+fn side_material(side: usize) -> i32 {
+    (Piece::P as usize..=Piece::Q as usize).map(|piece| piece_count(side * 6 + piece) * MATERIAL_SCORE[piece]).sum()
+}
+
+// lone king against mating material
+fn evaluate_kxk(strong: usize) -> i32 {
+    let strong_king = king_square(strong);
+    let weak_king = king_square(strong ^ 1);
+
+    KNOWN_WIN + side_material(strong) + push_to_edge(weak_king) + push_close(strong_king, weak_king)
+}
+
+// king, bishop & knight against king: mate in the corner of the bishop's color
+fn evaluate_kbnk(strong: usize) -> i32 {
+    let strong_king = king_square(strong);
+    let weak_king = king_square(strong ^ 1);
+
+    let bishop_square = unsafe { PIECE_BITBOARDS[strong * 6 + Piece::B as usize] }.trailing_zeros() as usize;
+
+    // a1 & h8 are dark squares
+    let corners = if (bishop_square / 8 + bishop_square % 8).is_multiple_of(2) { [0, 63] } else { [7, 56] };
+
+    let corner_distance = square_distance(weak_king, corners[0]).min(square_distance(weak_king, corners[1]));
+
+    KNOWN_WIN + side_material(strong) + 40 * (7 - corner_distance) + push_close(strong_king, weak_king)
+}
+
+// king & queen against king & rook
+fn evaluate_kqkr(strong: usize) -> i32 {
+    let strong_king = king_square(strong);
+    let weak_king = king_square(strong ^ 1);
+
+    MATERIAL_SCORE[Piece::Q as usize] - MATERIAL_SCORE[Piece::R as usize] + push_to_edge(weak_king) + push_close(strong_king, weak_king)
+}
+
+// king & pawn against king from the bitbase
+fn evaluate_kpk(strong: usize) -> i32 {
+    let pawn_square = unsafe { PIECE_BITBOARDS[strong * 6 + Piece::P as usize] }.trailing_zeros() as usize;
+
+    if kpk_probe(strong) {
+        KNOWN_WIN + MATERIAL_SCORE[Piece::P as usize] + 10 * relative_rank(pawn_square, strong) as i32
+    }else {
+        0
+    }
+}
+
+// can the side force mate against a lone king
+fn has_mating_material(side: usize) -> bool {
+    let bishops = unsafe { PIECE_BITBOARDS[side * 6 + Piece::B as usize] };
+
+    piece_count(side * 6 + Piece::Q as usize) > 0
+    || piece_count(side * 6 + Piece::R as usize) > 0
+    || (bishops != 0 && piece_count(side * 6 + Piece::N as usize) > 0)
+    // bishops on both square colors
+    || (bishops & DARK_SQUARES != 0 && bishops & !DARK_SQUARES != 0)
+}
+
+// dark squares (a1, c1, ... h8)
+const DARK_SQUARES: u64 = 0xAA55AA55AA55AA55;
+
+// specialised evaluation of a known endgame from white's point of view
+pub fn evaluate() -> Option<i32> {
+    let key = material_signature();
+
+    for (endgame_key, evaluate_endgame) in ENDGAMES.iter() {
+        if key == *endgame_key {
+            return Some(evaluate_endgame(PieceColor::WHITE as usize));
+        }
+
+        if key == flip_signature(*endgame_key) {
+            return Some(-evaluate_endgame(PieceColor::BLACK as usize));
+        }
+    }
+
+    // lone king against mating material
+    for strong in [PieceColor::WHITE as usize, PieceColor::BLACK as usize] {
+        let weak = strong ^ 1;
+
+        if unsafe { OCCUPANCIES[weak] == PIECE_BITBOARDS[weak * 6 + Piece::K as usize] } && has_mating_material(strong) {
+            let score = evaluate_kxk(strong);
+
+            return Some(if strong == PieceColor::WHITE as usize { score } else { -score });
+        }
+    }
+
+    None
+}
+
+// non-pawn pieces of a side (kings excluded)
+fn non_pawn_pieces(side: usize) -> i32 {
+    (Piece::N as usize..=Piece::Q as usize).map(|piece| piece_count(side * 6 + piece)).sum()
+}
+
+// scale factor of the endgame score for drawish material
+pub fn scale_factor() -> i32 {
+    unsafe {
+        let white_bishops = PIECE_BITBOARDS[Piece::B as usize];
+        let black_bishops = PIECE_BITBOARDS[Piece::b as usize];
+
+        // opposite-coloured bishops
+        if count_bits(white_bishops) == 1 && count_bits(black_bishops) == 1
+        && ((white_bishops & DARK_SQUARES == 0) != (black_bishops & DARK_SQUARES == 0))
+        {
+            if non_pawn_pieces(PieceColor::WHITE as usize) == 1 && non_pawn_pieces(PieceColor::BLACK as usize) == 1 {
+                return SCALE_OPPOSITE_BISHOPS;
+            }
+
+            return SCALE_OPPOSITE_BISHOPS_WITH_PIECES;
+        }
+
+        // bishop & rook pawns that can't promote on the bishop's color
+        for strong in [PieceColor::WHITE as usize, PieceColor::BLACK as usize] {
+            let weak = strong ^ 1;
+            let pawns = PIECE_BITBOARDS[strong * 6 + Piece::P as usize];
+            let bishops = PIECE_BITBOARDS[strong * 6 + Piece::B as usize];
+
+            if pawns == 0 || count_bits(bishops) != 1 || non_pawn_pieces(strong) != 1 || non_pawn_pieces(weak) != 0 {
+                continue;
+            }
+
+            for file in [0, 7] {
+                if pawns & FILE_MASKS[file] != pawns {
+                    continue;
+                }
+
+                let queening_square = if strong == PieceColor::WHITE as usize { 56 + file } else { file };
+
+                if (bishops & DARK_SQUARES == 0) != (DARK_SQUARES & (1u64 << queening_square) == 0)
+                && square_distance(king_square(weak), queening_square) <= 1
+                {
+                    return SCALE_DRAW;
+                }
+            }
+        }
+    }
+
+    SCALE_NORMAL
+}
+
+// scale the endgame half of a score
+pub fn scale(score: Score, scale_factor: i32) -> Score {
+    Score::new(score.opening(), score.endgame() * scale_factor / SCALE_NORMAL)
+}
+
+// dead draw: KK, KBK, KNK or KB vs KB with bishops on the same square color
+pub fn is_insufficient_material() -> bool {
+    unsafe {
+        let heavy_pieces = PIECE_BITBOARDS[Piece::P as usize] | PIECE_BITBOARDS[Piece::p as usize]
+            | PIECE_BITBOARDS[Piece::R as usize] | PIECE_BITBOARDS[Piece::r as usize]
+            | PIECE_BITBOARDS[Piece::Q as usize] | PIECE_BITBOARDS[Piece::q as usize];
+
+        if heavy_pieces != 0 {
+            return false;
+        }
+
+        let knights = PIECE_BITBOARDS[Piece::N as usize] | PIECE_BITBOARDS[Piece::n as usize];
+        let bishops = PIECE_BITBOARDS[Piece::B as usize] | PIECE_BITBOARDS[Piece::b as usize];
+
+        match (count_bits(knights), count_bits(bishops)) {
+            (0, 0) | (1, 0) | (0, 1) => true,
+            // one bishop each on the same square color
+            (0, 2) => count_bits(PIECE_BITBOARDS[Piece::B as usize]) == 1
+                && (bishops & DARK_SQUARES == 0 || bishops & !DARK_SQUARES == 0),
+            _ => false,
+        }
+    }
+}
+
+// KPK bitbase index (white pawn on files a-d)
+fn kpk_index(side: usize, weak_king: usize, strong_king: usize, pawn_square: usize) -> usize {
+    let pawn_index = (pawn_square / 8 - 1) * 4 + pawn_square % 8;
+
+    side + 2 * (weak_king + 64 * (strong_king + 64 * pawn_index))
+}
+
+// KPK position classification
+const KPK_INVALID: u8 = 0;
+const KPK_UNKNOWN: u8 = 1;
+const KPK_DRAW: u8 = 2;
+const KPK_WIN: u8 = 4;
+
+// classify a KPK position by its immediate outcome
+fn kpk_initial(side: usize, weak_king: usize, strong_king: usize, pawn_square: usize) -> u8 {
+    unsafe {
+        let pawn_attacks = PAWN_ATTACKS[PieceColor::WHITE as usize][pawn_square];
+
+        if weak_king == strong_king || weak_king == pawn_square || strong_king == pawn_square
+        || square_distance(weak_king, strong_king) <= 1
+        || (side == PieceColor::WHITE as usize && get_bit!(pawn_attacks, weak_king) == 1)
+        {
+            return KPK_INVALID;
+        }
+
+        if side == PieceColor::WHITE as usize {
+            // promotion the weak king can't stop
+            if pawn_square / 8 == 6 {
+                let push_square = pawn_square + 8;
+
+                if push_square != weak_king && push_square != strong_king
+                && (square_distance(weak_king, push_square) > 1 || square_distance(strong_king, push_square) == 1)
+                {
+                    return KPK_WIN;
+                }
+            }
+        }else {
+            let moves = KING_ATTACKS[weak_king] & !(KING_ATTACKS[strong_king] | pawn_attacks);
+
+            // stalemate or mate
+            if moves == 0 {
+                return if get_bit!(pawn_attacks, weak_king) == 1 { KPK_WIN } else { KPK_DRAW };
+            }
+
+            // undefended pawn is captured
+            if get_bit!(KING_ATTACKS[weak_king], pawn_square) == 1 && get_bit!(KING_ATTACKS[strong_king], pawn_square) == 0 {
+                return KPK_DRAW;
+            }
+        }
+    }
+
+    KPK_UNKNOWN
+}
+
+// classify a KPK position from its successors
+fn kpk_classify(database: &[u8], side: usize, weak_king: usize, strong_king: usize, pawn_square: usize) -> u8 {
+    let mut results = Vec::new();
+
+    unsafe {
+        if side == PieceColor::WHITE as usize {
+            let mut moves = KING_ATTACKS[strong_king] & !KING_ATTACKS[weak_king];
+
+            while moves != 0 {
+                let square = moves.trailing_zeros() as usize;
+
+                if square != pawn_square {
+                    results.push(database[kpk_index(PieceColor::BLACK as usize, weak_king, square, pawn_square)]);
+                }
+
+                reset_bit!(moves, square);
+            }
+
+            // pawn pushes (promotions are classified by kpk_initial)
+            let push_square = pawn_square + 8;
+
+            if pawn_square / 8 < 6 && push_square != weak_king && push_square != strong_king {
+                results.push(database[kpk_index(PieceColor::BLACK as usize, weak_king, strong_king, push_square)]);
+
+                let double_push_square = push_square + 8;
+
+                if pawn_square / 8 == 1 && double_push_square != weak_king && double_push_square != strong_king {
+                    results.push(database[kpk_index(PieceColor::BLACK as usize, weak_king, strong_king, double_push_square)]);
+                }
+            }
+
+            if results.contains(&KPK_WIN) {
+                KPK_WIN
+            }else if results.iter().all(|&result| result == KPK_DRAW || result == KPK_INVALID) {
+                KPK_DRAW
+            }else {
+                KPK_UNKNOWN
+            }
+        }else {
+            let mut moves = KING_ATTACKS[weak_king] & !(KING_ATTACKS[strong_king] | PAWN_ATTACKS[PieceColor::WHITE as usize][pawn_square]);
+
+            while moves != 0 {
+                let square = moves.trailing_zeros() as usize;
+
+                if square != pawn_square {
+                    results.push(database[kpk_index(PieceColor::WHITE as usize, square, strong_king, pawn_square)]);
+                }
+
+                reset_bit!(moves, square);
+            }
+
+            if results.contains(&KPK_DRAW) {
+                KPK_DRAW
+            }else if results.iter().all(|&result| result == KPK_WIN || result == KPK_INVALID) {
+                KPK_WIN
+            }else {
+                KPK_UNKNOWN
+            }
+        }
+    }
+}
+
+// decode a KPK bitbase index into (side, weak king, strong king, pawn square)
+fn kpk_decode(index: usize) -> (usize, usize, usize, usize) {
+    let side = index % 2;
+    let weak_king = (index / 2) % 64;
+    let strong_king = (index / 128) % 64;
+    let pawn_index = index / 8192;
+
+    (side, weak_king, strong_king, (pawn_index / 4 + 1) * 8 + pawn_index % 4)
+}
+
+// generate the KPK bitbase by retrograde iteration
+pub fn init_kpk_bitbase() {
+    let mut database = (0..KPK_SIZE).map(|index| {
+        let (side, weak_king, strong_king, pawn_square) = kpk_decode(index);
+
+        kpk_initial(side, weak_king, strong_king, pawn_square)
+    }).collect::<Vec<u8>>();
+
+    let mut changed = true;
+
+    while changed {
+        changed = false;
+
+        for index in 0..KPK_SIZE {
+            if database[index] != KPK_UNKNOWN {
+                continue;
+            }
+
+            let (side, weak_king, strong_king, pawn_square) = kpk_decode(index);
+            let result = kpk_classify(&database, side, weak_king, strong_king, pawn_square);
+
+            if result != KPK_UNKNOWN {
+                database[index] = result;
+                changed = true;
+            }
+        }
+    }
+
+    // positions still unknown can't be won
+    unsafe {
+        for (index, result) in database.iter().enumerate() {
+            KPK_BITBASE[index] = *result == KPK_WIN;
+        }
+    }
+}
+
+// is the KPK position won by the strong side
+fn kpk_probe(strong: usize) -> bool {
+    let mut side = unsafe { SIDE } as usize;
+    let mut strong_king = king_square(strong);
+    let mut weak_king = king_square(strong ^ 1);
+    let mut pawn_square = unsafe { PIECE_BITBOARDS[strong * 6 + Piece::P as usize] }.trailing_zeros() as usize;
+
+    // strong side as white
+    if strong == PieceColor::BLACK as usize {
+        strong_king ^= 56;
+        weak_king ^= 56;
+        pawn_square ^= 56;
+        side ^= 1;
+    }
+
+    // pawn on files a-d
+    if pawn_square % 8 >= 4 {
+        strong_king ^= 7;
+        weak_king ^= 7;
+        pawn_square ^= 7;
+    }
+
+    // pawns on the first or last rank are not in the bitbase
+    if pawn_square / 8 == 0 || pawn_square / 8 == 7 {
+        return false;
+    }
+
+    unsafe { KPK_BITBASE[kpk_index(side, weak_king, strong_king, pawn_square)] }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kpk_bitbase() {
+        let (_lock, char_pieces) = init_test();
+
+        let positions = [
+            // king on the sixth rank in front of the pawn
+            ("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1", true),
+            ("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1", true),
+            // the weak king can't catch the pawn
+            ("7k/8/P7/8/8/8/8/7K w - - 0 1", true),
+            ("k7/8/8/8/8/8/7P/K7 w - - 0 1", true),
+            // the weak king in the corner of a rook pawn
+            ("k7/8/8/PK6/8/8/8/8 w - - 0 1", false),
+            ("8/8/8/8/8/8/7p/k6K b - - 0 1", false),
+            // the undefended pawn is captured
+            ("8/8/8/4k3/4P3/8/8/7K b - - 0 1", false),
+            // black pawn, mirrored into the bitbase
+            ("8/8/8/8/4p3/4k3/8/4K3 w - - 0 1", true),
+        ];
+
+        for (fen, win) in positions {
+            parse_fen(fen, &char_pieces);
+
+            let strong = if unsafe { PIECE_BITBOARDS[Piece::P as usize] } != 0 { PieceColor::WHITE } else { PieceColor::BLACK } as usize;
+
+            assert_eq!(kpk_probe(strong), win, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn kbnk_drives_to_the_bishop_corner() {
+        let (_lock, char_pieces) = init_test();
+
+        // dark-squared bishop: a1 & h8 are the mating corners
+        parse_fen("7k/8/8/3K4/8/8/8/1NB5 w - - 0 1", &char_pieces);
+        let bishop_corner = evaluate().unwrap();
+
+        parse_fen("k7/8/8/3K4/8/8/8/1NB5 w - - 0 1", &char_pieces);
+        let wrong_corner = evaluate().unwrap();
+
+        assert!(bishop_corner > wrong_corner, "{} <= {}", bishop_corner, wrong_corner);
+
+        // same position with colors swapped
+        parse_fen("1nb5/8/8/8/3k4/8/8/7K b - - 0 1", &char_pieces);
+        assert_eq!(evaluate().unwrap(), -bishop_corner);
+    }
+
+    #[test]
+    fn scale_factors() {
+        let (_lock, char_pieces) = init_test();
+
+        let positions = [
+            (START_POSTITION, SCALE_NORMAL),
+            // opposite-coloured bishops
+            ("4k3/8/3b4/8/3P4/3B4/8/4K3 w - - 0 1", SCALE_OPPOSITE_BISHOPS),
+            ("4k3/8/3bn3/8/3P4/3BN3/8/4K3 w - - 0 1", SCALE_OPPOSITE_BISHOPS_WITH_PIECES),
+            // same-coloured bishops
+            ("4k3/8/2b5/8/3P4/3B4/8/4K3 w - - 0 1", SCALE_NORMAL),
+            // wrong bishop & rook pawn with the weak king in the corner
+            ("6k1/8/8/7P/8/3B4/8/4K3 w - - 0 1", SCALE_DRAW),
+            ("4k3/8/8/8/8/1b6/p7/1K6 w - - 0 1", SCALE_DRAW),
+            // the weak king is too far away or the bishop controls the queening square
+            ("k7/8/8/7P/8/3B4/8/4K3 w - - 0 1", SCALE_NORMAL),
+            ("6k1/8/8/7P/8/8/8/2B1K3 w - - 0 1", SCALE_NORMAL),
+        ];
+
+        for (fen, scale_factor_expected) in positions {
+            parse_fen(fen, &char_pieces);
+
+            assert_eq!(scale_factor(), scale_factor_expected, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn insufficient_material() {
+        let (_lock, char_pieces) = init_test();
+
+        let positions = [
+            ("4k3/8/8/8/8/8/8/4K3 w - - 0 1", true),
+            ("4k3/8/8/8/8/8/8/2B1K3 w - - 0 1", true),
+            ("4k3/8/8/8/8/8/8/1N2K3 w - - 0 1", true),
+            ("2b1k3/8/8/8/8/8/8/4K3 w - - 0 1", true),
+            // bishops on the same square color
+            ("4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1", true),
+            // mates are still possible
+            ("4k3/8/8/8/8/8/8/1NB1K3 w - - 0 1", false),
+            ("1n2k3/8/8/8/8/8/8/2B1K3 w - - 0 1", false),
+            ("1n2k3/8/8/8/8/8/8/1N2K3 w - - 0 1", false),
+            ("2b1k3/8/8/8/8/8/8/2B1K3 w - - 0 1", false),
+            ("4k3/8/8/8/8/8/8/1N2K1N1 w - - 0 1", false),
+            ("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", false),
+        ];
+
+        for (fen, insufficient) in positions {
+            parse_fen(fen, &char_pieces);
+
+            assert_eq!(is_insufficient_material(), insufficient, "{}", fen);
+        }
+    }
+}
@@ -0,0 +1,231 @@
+/*
+    EPD test suites (WAC, STS, ECM, ...)
+
+    epd <file> [depth D] [movetime MS]
+
+    Every line holds the four FEN fields followed by operations ending
+    with a semicolon:
+        r1b2rk1/ppq1bppp/2n1pn2/3p4/3P4/2PBPN2/PP1N1PPP/R2QK2R w KQ - bm e4; id "test.001";
+
+    Each position is searched from an empty hash table for a fixed depth
+    or time. It is solved when the best move is one of the bm (best
+    move) moves & none of the am (avoid move) moves. Moves are written
+    in SAN, UCI moves are accepted too. Positions without bm or am or
+    with an invalid FEN are skipped.
+*/
+
+use super::*;
+
+// time per position when no limit is given
+pub const DEFAULT_MOVETIME: u64 = 1000;
+
+// search limit of every position
+#[derive(Copy, Clone)]
+pub enum Limit {
+    Depth(usize),
+    MoveTime(u64),
+}
+
+struct EpdPosition {
+    fen: String,
+    operations: Vec<(String, Vec<String>)>,
+}
+
+impl EpdPosition {
+    fn operands(&self, opcode: &str) -> Option<&Vec<String>> {
+        self.operations.iter().find(|(name, _)| name == opcode).map(|(_, operands)| operands)
+    }
+}
+
+// split operations on semicolons & operands on spaces, quoted strings stay whole
+fn parse_operations(text: &str) -> Vec<(String, Vec<String>)> {
+    let mut operations = Vec::new();
+    let mut words: Vec<String> = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+
+    for c in text.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' | ' ' | '\t' if !quoted => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+
+                if c == ';' && !words.is_empty() {
+                    let opcode = words.remove(0);
+                    operations.push((opcode, std::mem::take(&mut words)));
+                }
+            },
+            _ => word.push(c),
+        }
+    }
+
+    // last operation without a semicolon
+    if !word.is_empty() {
+        words.push(word);
+    }
+
+    if !words.is_empty() {
+        let opcode = words.remove(0);
+        operations.push((opcode, words));
+    }
+
+    operations
+}
+
+fn parse_line(line: &str) -> Option<EpdPosition> {
+    let line = line.trim();
+
+    let fields = line.splitn(5, char::is_whitespace).collect::<Vec<&str>>();
+
+    if fields.len() < 4 {
+        return None;
+    }
+
+    let operations = parse_operations(fields.get(4).unwrap_or(&""));
+
+    // move counters are given by the hmvc & fmvn operations
+    let counter = |opcode: &str, default: &str| {
+        operations.iter()
+            .find(|(name, operands)| name == opcode && operands.len() == 1 && operands[0].parse::<u32>().is_ok())
+            .map(|(_, operands)| operands[0].clone())
+            .unwrap_or(default.to_string())
+    };
+
+    let fen = format!("{} {} {}", fields[..4].join(" "), counter("hmvc", "0"), counter("fmvn", "1"));
+
+    Some(EpdPosition { fen, operations })
+}
+
+// moves of the current position, None when one of them is not legal
+fn parse_moves(moves: &[String]) -> Option<Vec<u64>> {
+    moves.iter().map(|mv| pgn::parse_san(mv).or_else(|| parse_move(mv).ok())).collect()
+}
+
+fn search(limit: Limit, ht: &mut HashMap<u64, TTEntry>) {
+    ht.clear();
+    clear_history();
+    clear_pawn_hash();
+    reset_time_control();
+
+    let depth = match limit {
+        Limit::Depth(depth) => depth.min(MAX_PLY - 1),
+        Limit::MoveTime(movetime) => {
+            unsafe {
+                STARTTIME = get_time_ms();
+                STOPTIME = STARTTIME + movetime;
+                TIMESET = 1;
+            }
+
+            MAX_PLY - 1
+        },
+    };
+
+    search_position(depth, ht);
+}
+
+pub fn run_suite(path: &str, limit: Limit, char_pieces: &HashMap<char, u32>, ht: &mut HashMap<u64, TTEntry>) {
+    let text = match std::fs::read(path) {
+        Ok(text) => String::from_utf8_lossy(&text).to_string(),
+        Err(e) => {
+            println!("info string cannot read {}: {}", path, e);
+            return;
+        }
+    };
+
+    let positions = text.lines().filter_map(parse_line).collect::<Vec<EpdPosition>>();
+
+    let mut solved = 0;
+    let mut tested = 0;
+
+    let start = get_time_ms();
+
+    unsafe {
+        SEARCH_OUTPUT = false;
+    }
+
+    for (index, position) in positions.iter().enumerate() {
+        let id = match position.operands("id") {
+            Some(operands) if !operands.is_empty() => operands.join(" "),
+            _ => format!("#{}", index + 1),
+        };
+
+        if let Err(e) = try_parse_fen(&position.fen, char_pieces) {
+            println!("{:4} {:<16} skipped ({})", index + 1, id, e);
+            continue;
+        }
+
+        let best_moves = parse_moves(position.operands("bm").map_or(&[][..], |operands| &operands[..]));
+        let avoid_moves = parse_moves(position.operands("am").map_or(&[][..], |operands| &operands[..]));
+
+        let (best_moves, avoid_moves) = match (best_moves, avoid_moves) {
+            (Some(best), Some(avoid)) if !best.is_empty() || !avoid.is_empty() => (best, avoid),
+            (Some(_), Some(_)) => {
+                println!("{:4} {:<16} skipped (no bm or am)", index + 1, id);
+                continue;
+            },
+            _ => {
+                println!("{:4} {:<16} skipped (illegal bm or am)", index + 1, id);
+                continue;
+            },
+        };
+
+        // expected moves in SAN, written before searching
+        let expected = if best_moves.is_empty() {
+            format!("am {}", avoid_moves.iter().map(|&mv| pgn::move_to_san(mv)).collect::<Vec<String>>().join(" "))
+        }else {
+            format!("bm {}", best_moves.iter().map(|&mv| pgn::move_to_san(mv)).collect::<Vec<String>>().join(" "))
+        };
+
+        let position_start = get_time_ms();
+
+        search(limit, ht);
+
+        let (best_move, nodes) = unsafe { (BEST_MOVE, NODES) };
+
+        let found = match best_move {
+            0 => String::from("none"),
+            mv => pgn::move_to_san(mv),
+        };
+
+        let is_solved = best_move != 0
+            && (best_moves.is_empty() || best_moves.contains(&best_move))
+            && !avoid_moves.contains(&best_move);
+
+        tested += 1;
+
+        if is_solved {
+            solved += 1;
+        }
+
+        println!("{:4} {:<16} {:<8} found {:<8} {} nodes {} time {}", index + 1, id, if is_solved { "solved" } else { "failed" },
+            found, expected, nodes, get_time_ms() - position_start);
+    }
+
+    unsafe {
+        SEARCH_OUTPUT = true;
+    }
+
+    let percentage = if tested > 0 { solved as f64 * 100.0 / tested as f64 } else { 0.0 };
+
+    println!("solved {} of {} ({:.1}%) time {}", solved, tested, percentage, get_time_ms() - start);
+}
+
+// run the epd command
+pub fn run(args: &[&str], char_pieces: &HashMap<char, u32>, ht: &mut HashMap<u64, TTEntry>) {
+    let value = |name: &str| {
+        args.iter().position(|arg| *arg == name).and_then(|index| args.get(index + 1)).and_then(|val| val.parse::<u64>().ok())
+    };
+
+    let limit = match (value("depth"), value("movetime")) {
+        (Some(depth), _) => Limit::Depth(depth as usize),
+        (None, Some(movetime)) => Limit::MoveTime(movetime),
+        (None, None) => Limit::MoveTime(DEFAULT_MOVETIME),
+    };
+
+    match args.first() {
+        Some(path) => run_suite(path, limit, char_pieces, ht),
+        None => println!("info string usage: epd <file> [depth D] [movetime MS]"),
+    }
+}
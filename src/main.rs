@@ -4,6 +4,21 @@ use std::io::{self, Write};
 use libc::FILE;
 use regex::Regex;
 
+mod bench;
+mod cli;
+mod endgame;
+mod epd;
+mod pgn;
+mod polyglot;
+mod sprt;
+mod tournament;
+
+#[cfg(feature = "nnue")]
+mod nnue;
+
+#[cfg(feature = "stats")]
+mod stats;
+
 
 #[derive(Debug)]
 enum Error {
@@ -15,6 +30,88 @@ enum Error {
 // Define bitboard type
 type BitBoard = u64;
 
+/*
+    Packed evaluation score: opening (middlegame) value in the lower
+    16 bits & endgame value in the upper 16 bits of a single integer,
+    so both halves of every evaluation term are added in one go.
+*/
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+struct Score(i32);
+
+impl Score {
+    const fn new(opening: i32, endgame: i32) -> Score {
+        Score(endgame.wrapping_shl(16).wrapping_add(opening))
+    }
+
+    // opening (middlegame) half
+    fn opening(self) -> i32 {
+        self.0 as i16 as i32
+    }
+
+    // endgame half
+    fn endgame(self) -> i32 {
+        (self.0.wrapping_add(0x8000) >> 16) as i16 as i32
+    }
+
+    // interpolate between opening & endgame values for a given game phase score
+    fn taper(self, game_phase_score: i32) -> i32 {
+        /*
+            The phase is clamped to [ENDGAME_PHASE_SCORE, OPENING_PHASE_SCORE]
+            and the score slides linearly between both halves, so trading a
+            piece never makes the evaluation jump:
+
+            (
+              opening * (phase - endgame_phase_score) +
+              endgame * (opening_phase_score - phase)
+            ) / (opening_phase_score - endgame_phase_score)
+        */
+        let phase = game_phase_score.clamp(ENDGAME_PHASE_SCORE, OPENING_PHASE_SCORE);
+
+        (self.opening() * (phase - ENDGAME_PHASE_SCORE) + self.endgame() * (OPENING_PHASE_SCORE - phase))
+            / (OPENING_PHASE_SCORE - ENDGAME_PHASE_SCORE)
+    }
+}
+
+impl std::ops::Add for Score {
+    type Output = Score;
+    fn add(self, other: Score) -> Score {
+        Score(self.0.wrapping_add(other.0))
+    }
+}
+
+impl std::ops::Sub for Score {
+    type Output = Score;
+    fn sub(self, other: Score) -> Score {
+        Score(self.0.wrapping_sub(other.0))
+    }
+}
+
+impl std::ops::Neg for Score {
+    type Output = Score;
+    fn neg(self) -> Score {
+        Score(self.0.wrapping_neg())
+    }
+}
+
+impl std::ops::Mul<i32> for Score {
+    type Output = Score;
+    fn mul(self, factor: i32) -> Score {
+        Score(self.0.wrapping_mul(factor))
+    }
+}
+
+impl std::ops::AddAssign for Score {
+    fn add_assign(&mut self, other: Score) {
+        *self = *self + other;
+    }
+}
+
+impl std::ops::SubAssign for Score {
+    fn sub_assign(&mut self, other: Score) {
+        *self = *self - other;
+    }
+}
+
 // Board squares
 #[repr(u64)]
 #[allow(non_camel_case_types)]
@@ -101,18 +198,36 @@ static mut PLY: usize = 0;
 // best move so far
 static mut BEST_MOVE: u64 = 0; 
 
+// score of the last completed search iteration
+static mut BEST_SCORE: i32 = 0;
+
 // NODES searched in a given position
 static mut NODES: usize = 0;
 
 // HASH KEY of the posiiton
 static mut HASH_KEY: u64 = 0;
 
+// hash key of the pawns only (pawn structure cache)
+static mut PAWN_KEY: u64 = 0;
+
+// material & positional score (updated incrementally)
+static mut EVAL_SCORE: Score = Score(0);
+
+// game phase score (updated incrementally)
+static mut PHASE_SCORE: i32 = 0;
+
 static MATE_VALUE: i32 = 49000;
 
 static MATE_SCORE: i32 = 48000;
 
 static INFINITY: i32 = 50000;
 
+// maximum search ply (size of every per-ply search structure)
+const MAX_PLY: usize = 128;
+
+// upper bound on the legal moves of a position (218 is the most known)
+const MAX_MOVES: usize = 256;
+
 // positions repetition table
 static mut REPETITION_TABLE: [u64; 1000] = [0; 1000];
 
@@ -275,9 +390,27 @@ static MVV_LVA: [[usize; 12]; 12] = [
 // Killer & History moves
 
 // killer moves [id][ply]
-static mut KILLER_MOVES: [[usize; 64];2] = [[0; 64]; 2];
+static mut KILLER_MOVES: [[usize; MAX_PLY];2] = [[0; MAX_PLY]; 2];
 // history moves [piece][square]
-static mut HISTORY_MOVES: [[usize; 64];12] = [[0; 64]; 12];
+static mut HISTORY_MOVES: [[i32; 64];12] = [[0; 64]; 12];
+
+// counter moves [previous move piece][previous move target square]
+static mut COUNTER_MOVES: [[u64; 64]; 12] = [[0; 64]; 12];
+
+// continuation history [previous piece][previous target square][piece][target square]
+static mut CONTINUATION_HISTORY: [[[[i32; 64]; 12]; 64]; 12] = [[[[0; 64]; 12]; 64]; 12];
+
+// capture history [piece][target square][captured piece]
+static mut CAPTURE_HISTORY: [[[i32; 12]; 64]; 12] = [[[0; 12]; 64]; 12];
+
+// moves played at each ply of the current search line (0 for null move)
+static mut MOVE_STACK: [u64; MAX_PLY] = [0; MAX_PLY];
+
+// history scores are kept within [-HISTORY_MAX, HISTORY_MAX]
+static HISTORY_MAX: i32 = 16384;
+
+// maximum history bonus (malus) given by a single update
+static HISTORY_BONUS_MAX: i32 = 1536;
 
 // file masks [square]
 static mut FILE_MASKS: [u64; 64] = [0; 64];
@@ -306,37 +439,67 @@ static GET_RANK: [usize; 64] = [
 ];
 
 // double pawns penalty
-static DOUBLE_PAWN_PENALTY: i32 = -10;
-static DOUBLE_PAWN_PENALTY_OPENING: i32 = -5;
-static DOUBLE_PAWN_PENALTY_ENDGAME: i32 = -10;
+static DOUBLE_PAWN_PENALTY: Score = Score::new(-5, -10);
 
 // isolated pawn penalty
-static ISOLATED_PAWN_PENALTY: i32 = -10;
-static ISOLATED_PAWN_PENALTY_OPENING: i32 = -5;
-static ISOLATED_PAWN_PENALTY_ENDGAME: i32 = -10;
+static ISOLATED_PAWN_PENALTY: Score = Score::new(-5, -10);
+
+// passed pawn bonus [relative rank]
+static PASSED_PAWN_BONUS: [Score; 8] = [
+    Score::new(0, 0), Score::new(10, 10), Score::new(30, 30), Score::new(50, 50),
+    Score::new(75, 75), Score::new(100, 100), Score::new(150, 150), Score::new(200, 200),
+];
 
-// passed pawn bonus
-static WHITE_PASSED_PAWN_BONUS: [i32; 8] = [0, 10, 30, 50, 75, 100, 150, 200];
+// passed pawn king distances to the square in front of the pawn (per relative rank)
+static PASSED_PAWN_ENEMY_KING_DISTANCE: Score = Score::new(0, 4);
+static PASSED_PAWN_OWN_KING_DISTANCE: Score = Score::new(0, -2);
 
-static BLACK_PASSED_PAWN_BONUS: [i32; 8] = [200, 150, 100, 75, 50, 30, 10, 0];
+// passed pawn blocked by an enemy piece
+static PASSED_PAWN_BLOCKED: Score = Score::new(-10, -20);
 
 //semi open file score 
-static SEMI_OPEN_FILE_SCORE: i32 = 10;
+static SEMI_OPEN_FILE_SCORE: Score = Score::new(10, 10);
+
+static OPEN_FILE_SCORE: Score = Score::new(15, 15);
 
-static OPEN_FILE_SCORE: i32 = 15;
+// rook on the seventh rank (enemy king on the eighth or enemy pawns on the seventh)
+static ROOK_ON_SEVENTH: Score = Score::new(20, 40);
 
 // mobility units (values from engine Fruit reloaded)
+static KNIGHT_UNIT: i32 = 4;
 static BISHOP_UNIT: i32 = 4;
+static ROOK_UNIT: i32 = 7;
 static QUEEN_UNIT: i32 = 9;
 
 // mobility bonuses (values from engine Fruit reloaded)
-static BISHOP_MOBILITY_OPENING: i32 = 5;
-static BISHOP_MOBILITY_ENDGAME: i32 = 5;
-static QUEEN_MOBILITY_OPENING: i32 = 1;
-static QUEEN_MOBILITY_ENDGAME: i32 = 2;
+static KNIGHT_MOBILITY: Score = Score::new(4, 4);
+static BISHOP_MOBILITY: Score = Score::new(5, 5);
+static ROOK_MOBILITY: Score = Score::new(2, 4);
+static QUEEN_MOBILITY: Score = Score::new(1, 2);
+
+// bishop pair bonus
+static BISHOP_PAIR: Score = Score::new(30, 50);
+
+// knight on an outpost (supported by a pawn and out of reach of enemy pawns)
+static KNIGHT_OUTPOST: Score = Score::new(25, 15);
 
 // king safety bonus
-static KING_SHIELD_BONUS: i32 = 5;
+static KING_SHIELD_BONUS: Score = Score::new(5, 5);
+
+// king attack units per attacked king zone square [piece]
+static KING_ATTACK_WEIGHTS: [i32; 6] = [0, 2, 2, 3, 5, 0];
+
+// king attack penalty per attack unit
+static KING_ATTACK: Score = Score::new(-3, -1);
+
+// share of the king attack penalty (%) applied by number of attackers
+static KING_ATTACKERS_SCALE: [i32; 8] = [0, 0, 50, 75, 88, 94, 97, 99];
+
+// enemy piece attacked by a pawn
+static THREAT_BY_PAWN: Score = Score::new(40, 30);
+
+// undefended enemy piece attacked
+static THREAT_HANGING: Score = Score::new(20, 15);
 
 // material score [game phase][piece]
 static MATERIAL_SCORE_TP: [[i32; 12]; 2] = [
@@ -507,6 +670,65 @@ static POSITIONAL_SCORE: [[[i32;64]; 6]; 2] = [
     ]
 ];
 
+// tunable evaluation weights (defaults are the static tables above)
+#[derive(Copy, Clone, PartialEq)]
+struct EvalParams {
+    // material score [game phase][piece]
+    material: [[i32; 12]; 2],
+    // positional piece scores [game phase][piece][square]
+    positional: [[[i32; 64]; 6]; 2],
+    double_pawn_penalty: Score,
+    isolated_pawn_penalty: Score,
+    // [relative rank]
+    passed_pawn_bonus: [Score; 8],
+    passed_pawn_enemy_king_distance: Score,
+    passed_pawn_own_king_distance: Score,
+    passed_pawn_blocked: Score,
+    semi_open_file_score: Score,
+    open_file_score: Score,
+    rook_on_seventh: Score,
+    knight_mobility: Score,
+    bishop_mobility: Score,
+    rook_mobility: Score,
+    queen_mobility: Score,
+    bishop_pair: Score,
+    knight_outpost: Score,
+    king_shield_bonus: Score,
+    // [piece]
+    king_attack_weights: [i32; 6],
+    king_attack: Score,
+    threat_by_pawn: Score,
+    threat_hanging: Score,
+}
+
+static DEFAULT_EVAL_PARAMS: EvalParams = EvalParams {
+    material: MATERIAL_SCORE_TP,
+    positional: POSITIONAL_SCORE,
+    double_pawn_penalty: DOUBLE_PAWN_PENALTY,
+    isolated_pawn_penalty: ISOLATED_PAWN_PENALTY,
+    passed_pawn_bonus: PASSED_PAWN_BONUS,
+    passed_pawn_enemy_king_distance: PASSED_PAWN_ENEMY_KING_DISTANCE,
+    passed_pawn_own_king_distance: PASSED_PAWN_OWN_KING_DISTANCE,
+    passed_pawn_blocked: PASSED_PAWN_BLOCKED,
+    semi_open_file_score: SEMI_OPEN_FILE_SCORE,
+    open_file_score: OPEN_FILE_SCORE,
+    rook_on_seventh: ROOK_ON_SEVENTH,
+    knight_mobility: KNIGHT_MOBILITY,
+    bishop_mobility: BISHOP_MOBILITY,
+    rook_mobility: ROOK_MOBILITY,
+    queen_mobility: QUEEN_MOBILITY,
+    bishop_pair: BISHOP_PAIR,
+    knight_outpost: KNIGHT_OUTPOST,
+    king_shield_bonus: KING_SHIELD_BONUS,
+    king_attack_weights: KING_ATTACK_WEIGHTS,
+    king_attack: KING_ATTACK,
+    threat_by_pawn: THREAT_BY_PAWN,
+    threat_hanging: THREAT_HANGING,
+};
+
+// evaluation weights in use
+static mut EVAL_PARAMS: EvalParams = DEFAULT_EVAL_PARAMS;
+
 // set file or rank mask
 fn set_file_rank_mask(file_number: Option<i32>, rank_number: Option<i32>) -> u64 {
     let mut mask: u64 = 0;
@@ -535,6 +757,28 @@ fn set_file_rank_mask(file_number: Option<i32>, rank_number: Option<i32>) -> u64
     mask
 }
 
+// init late move reduction table
+fn init_lmr_table() {
+    /*
+        Reductions grow logarithmically both with the remaining depth
+        and with the number of moves already searched at the node:
+
+        reduction = 0.75 + ln(depth) * ln(moves searched) / 2.25
+    */
+    let mut table = [[0; MAX_MOVES]; MAX_PLY];
+
+    // no reduction at depth 0 or before the first move
+    for (depth, reductions) in table.iter_mut().enumerate().skip(1) {
+        for (moves_searched, reduction) in reductions.iter_mut().enumerate().skip(1) {
+            *reduction = (0.75 + (depth as f64).ln() * (moves_searched as f64).ln() / 2.25) as usize;
+        }
+    }
+
+    unsafe {
+        LMR_TABLE = table;
+    }
+}
+
 // init evaluation masks
 fn init_evaluation_masks() {
     unsafe {
@@ -625,12 +869,12 @@ fn init_evaluation_masks() {
 */
 
 // PV length
-static mut PV_LENGTH: [u64; 64] = [0; 64];
+static mut PV_LENGTH: [u64; MAX_PLY] = [0; MAX_PLY];
 
 // PV table
-static mut PV_TABLE: [[u64; 64]; 64] = [[0; 64]; 64];
+static mut PV_TABLE: [[u64; MAX_PLY]; MAX_PLY] = [[0; MAX_PLY]; MAX_PLY];
 
-static mut PREV_PV_TABLE: [[u64; 64]; 64] = [[0; 64]; 64];
+static mut PREV_PV_TABLE: [[u64; MAX_PLY]; MAX_PLY] = [[0; MAX_PLY]; MAX_PLY];
 
 static mut FOLLOW_PV: u64 = 0;
 
@@ -641,6 +885,48 @@ static mut SCORE_PV: u64 = 0;
 static FULL_DEPTH_MOVE: usize = 4;
 static REDUCTION_LIMIT: usize = 3;
 
+// late move reductions [depth][moves searched]
+static mut LMR_TABLE: [[usize; MAX_MOVES]; MAX_PLY] = [[0; MAX_MOVES]; MAX_PLY];
+
+// history score worth one ply of reduction
+static LMR_HISTORY_DIVISOR: i32 = 8192;
+
+// Singular extension constants
+static SINGULAR_DEPTH: usize = 8;
+static SINGULAR_TT_DEPTH_MARGIN: u64 = 3;
+static SINGULAR_MARGIN: i32 = 2;
+
+// move excluded from the search at a given ply (singular extension search)
+static mut EXCLUDED_MOVES: [u64; MAX_PLY] = [0; MAX_PLY];
+
+// Forward pruning constants
+
+// reverse futility pruning
+static RFP_DEPTH: usize = 6;
+static RFP_MARGIN: i32 = 90;
+
+// razoring
+static RAZOR_DEPTH: usize = 3;
+static RAZOR_MARGIN: i32 = 250;
+
+// futility pruning margins [depth]
+static FUTILITY_DEPTH: usize = 3;
+static FUTILITY_MARGIN: [i32; 4] = [0, 125, 250, 375];
+
+// late move pruning quiet move counts [depth]
+static LMP_DEPTH: usize = 4;
+static LMP_MOVE_COUNT: [usize; 5] = [0, 5, 8, 13, 20];
+
+// null move pruning
+static NULL_MOVE_REDUCTION: usize = 2;
+static NULL_MOVE_VERIFICATION_PIECES: usize = 2;
+
+// null move verification search in progress at a given ply
+static mut NULL_MOVE_VERIFICATION: [bool; MAX_PLY] = [false; MAX_PLY];
+
+// delta pruning safety margin (quiescence search)
+static DELTA_MARGIN: i32 = 200;
+
 /**********************************\
  ==================================
  
@@ -668,6 +954,22 @@ static mut TIMESET: u64 = 0;
 static mut STOPPED: u64 = 0;
 // variable to flag wether a node has been searched fully or not
 static mut SEARCH_COMPLETE: bool = true;
+// nodes to search before stopping (0 for no limit)
+static mut NODE_LIMIT: usize = 0;
+// print search info & best move (off for searches run by engine tools)
+static mut SEARCH_OUTPUT: bool = true;
+// poll stdin for stop & quit while searching (off when run from the command line)
+static mut LISTEN_INPUT: bool = true;
+
+// count a search statistic (empty without the stats feature)
+macro_rules! stat {
+    ($counter: ident) => {
+        #[cfg(feature = "stats")]
+        {
+            stats::STATS.$counter += 1;
+        }
+    };
+}
 
 /**********************************\
  ==================================
@@ -776,6 +1078,30 @@ fn generate_hash_key() -> u64 {
     }
 }
 
+// generate pawn structure hash key from scratch
+fn generate_pawn_key() -> u64 {
+    let mut pawn_key: u64 = 0;
+
+    unsafe {
+        for piece in [Piece::P as usize, Piece::p as usize] {
+            let mut bitboard = PIECE_BITBOARDS[piece];
+
+            while bitboard != 0 {
+                let square = match index_lsb(bitboard) {
+                    Ok(val) => val,
+                    Err(e) => panic!("invalid bitboard: {:?}", e),
+                };
+
+                pawn_key ^= PIECE_KEYS[piece][square];
+
+                reset_bit!(bitboard, square);
+            }
+        }
+    }
+
+    pawn_key
+}
+
 
 // transposition table hash flags
 static HASH_FLAG_EXACT: u64 = 0;
@@ -790,6 +1116,7 @@ struct TTEntry {
     depth: u64,
     flag: u64,
     score: i32,
+    best_move: u64,
 }
 
 
@@ -798,9 +1125,13 @@ struct TTEntry {
 // read hash entry data
 fn read_hash_entry(alpha: i32, beta: i32, depth: u64, ht: &HashMap<u64, TTEntry>) -> Option<i32> {
     unsafe {
+        stat!(tt_probes);
+
         if let Some(hash_entry) = ht.get(&HASH_KEY) {
             // make sure we're dealing with the exact position we need
             if hash_entry.hash_key == HASH_KEY {
+                stat!(tt_hits);
+
                 if hash_entry.depth >= depth {
                     // extract stored score from TT entry
                     let mut score: i32 = hash_entry.score ;
@@ -837,9 +1168,31 @@ fn read_hash_entry(alpha: i32, beta: i32, depth: u64, ht: &HashMap<u64, TTEntry>
     }
 }
 
+// read hash entry of the current position regardless of its bounds
+fn probe_hash_entry(ht: &HashMap<u64, TTEntry>) -> Option<TTEntry> {
+    unsafe {
+        let hash_key = HASH_KEY;
+
+        match ht.get(&hash_key) {
+            Some(hash_entry) if hash_entry.hash_key == hash_key => {
+                let mut hash_entry = *hash_entry;
+                // retrieve score independent from the actual path
+                if hash_entry.score < -MATE_SCORE {
+                    hash_entry.score += PLY as i32;
+                }
+                if hash_entry.score > MATE_SCORE {
+                    hash_entry.score -= PLY as i32;
+                }
+                Some(hash_entry)
+            },
+            _ => None,
+        }
+    }
+}
+
 
 // write hash entry data
-fn write_hash_entry(mut score: i32, depth: u64, hash_flag: u64, ht: &mut HashMap<u64, TTEntry>) {
+fn write_hash_entry(mut score: i32, depth: u64, hash_flag: u64, best_move: u64, ht: &mut HashMap<u64, TTEntry>) {
     unsafe {
 
         // store score independent from the actual path
@@ -857,7 +1210,8 @@ fn write_hash_entry(mut score: i32, depth: u64, hash_flag: u64, ht: &mut HashMap
             hash_key: HASH_KEY,
             depth,
             flag: hash_flag,
-            score }
+            score,
+            best_move }
         );
     }
 }
@@ -931,6 +1285,7 @@ macro_rules! get_move_target {
 }
 
 // extract piece
+#[macro_export]
 macro_rules! get_move_piece {
     ($move: expr) => {
         ($move & 0xf000) >> 12
@@ -938,6 +1293,7 @@ macro_rules! get_move_piece {
 }
 
 // extract promoted piece
+#[macro_export]
 macro_rules! get_move_promoted {
     ($move: expr) => {
         ($move & 0xf0000) >> 16
@@ -945,6 +1301,7 @@ macro_rules! get_move_promoted {
 }
 
 // extract capture flag
+#[macro_export]
 macro_rules! get_move_capture {
     ($move: expr) => {
         $move & 0x100000
@@ -966,6 +1323,7 @@ macro_rules! get_move_enpassant {
 }
 
 // extract enpassant flag
+#[macro_export]
 macro_rules! get_move_castling {
     ($move: expr) => {
         $move & 0x800000
@@ -1066,9 +1424,21 @@ fn read_input() {
 fn communicate() {
     // if time is up break here
     unsafe {
-        if TIMESET == 1 && get_time_ms() > STOPTIME {
+        // the first iteration always completes so there is a move to play
+        let has_move = PREV_PV_TABLE[0][0] != 0;
+
+        if has_move && TIMESET == 1 && get_time_ms() > STOPTIME {
+            STOPPED = 1;
+        }
+
+        // if node budget is spent
+        if has_move && NODE_LIMIT != 0 && NODES >= NODE_LIMIT {
             STOPPED = 1;
         }
+
+        if !LISTEN_INPUT {
+            return;
+        }
     }
 
     read_input();
@@ -1574,27 +1944,113 @@ fn print_board() {
     
 }
 
-fn copy_board() -> ([u64; 12], [u64; 3], i32, u32, u32, u64) {
+// FEN string of the current position (move counters are not tracked)
+fn get_fen() -> String {
+    let mut fen = String::new();
+
     unsafe {
-        let piece_bitboards_copy = PIECE_BITBOARDS;
-        let occupancy_copy = OCCUPANCIES;
-        let side_copy = SIDE;
-        let enpassant_copy = ENPASSANT;
-        let castle_copy = CASTLE;
-        let hash_key_copy = HASH_KEY;
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+
+            for file in 0..8 {
+                let square = rank * 8 + file;
+
+                match (Piece::P as usize..=Piece::k as usize).find(|&piece| get_bit!(PIECE_BITBOARDS[piece], square) == 1) {
+                    Some(piece) => {
+                        if empty > 0 {
+                            fen.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+
+                        fen.push_str(ASCII_PIECES[piece]);
+                    },
+                    None => empty += 1,
+                }
+            }
+
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+
+            if rank > 0 {
+                fen.push('/');
+            }
+        }
+
+        fen.push_str(if SIDE == PieceColor::WHITE as i32 { " w " } else { " b " });
+
+        if CASTLE == 0 {
+            fen.push('-');
+        }else {
+            for (right, symbol) in [(Castle::wk, 'K'), (Castle::wq, 'Q'), (Castle::bk, 'k'), (Castle::bq, 'q')] {
+                if CASTLE & right as u32 != 0 {
+                    fen.push(symbol);
+                }
+            }
+        }
+
+        if ENPASSANT != BoardSquare::no_sq as u32 {
+            fen.push_str(&format!(" {}", SQUARE_TO_COORD[ENPASSANT as usize]));
+        }else {
+            fen.push_str(" -");
+        }
+    }
+
+    fen.push_str(" 0 1");
+
+    fen
+}
+
+// board state preserved before making a move
+#[derive(Copy, Clone)]
+struct BoardCopy {
+    piece_bitboards: [u64; 12],
+    occupancies: [u64; 3],
+    side: i32,
+    enpassant: u32,
+    castle: u32,
+    hash_key: u64,
+    pawn_key: u64,
+    eval_score: Score,
+    phase_score: i32,
+    #[cfg(feature = "nnue")]
+    accumulator: nnue::Accumulator,
+}
 
-        return (piece_bitboards_copy, occupancy_copy, side_copy, enpassant_copy, castle_copy, hash_key_copy)
+fn copy_board() -> BoardCopy {
+    unsafe {
+        BoardCopy {
+            piece_bitboards: PIECE_BITBOARDS,
+            occupancies: OCCUPANCIES,
+            side: SIDE,
+            enpassant: ENPASSANT,
+            castle: CASTLE,
+            hash_key: HASH_KEY,
+            pawn_key: PAWN_KEY,
+            eval_score: EVAL_SCORE,
+            phase_score: PHASE_SCORE,
+            #[cfg(feature = "nnue")]
+            accumulator: nnue::ACCUMULATOR,
+        }
     }
 }
 
-fn take_back(piece_bitboards_copy: [u64; 12], occupancy_copy: [u64; 3], side_copy: i32, enpassant_copy: u32, castle_copy: u32, hash_key_copy: u64) {
+fn take_back(board_copy: BoardCopy) {
     unsafe {
-        PIECE_BITBOARDS = piece_bitboards_copy;
-        OCCUPANCIES = occupancy_copy;
-        SIDE =side_copy;
-        ENPASSANT = enpassant_copy;
-        CASTLE = castle_copy;
-        HASH_KEY = hash_key_copy;
+        PIECE_BITBOARDS = board_copy.piece_bitboards;
+        OCCUPANCIES = board_copy.occupancies;
+        SIDE = board_copy.side;
+        ENPASSANT = board_copy.enpassant;
+        CASTLE = board_copy.castle;
+        HASH_KEY = board_copy.hash_key;
+        PAWN_KEY = board_copy.pawn_key;
+        EVAL_SCORE = board_copy.eval_score;
+        PHASE_SCORE = board_copy.phase_score;
+
+        #[cfg(feature = "nnue")]
+        {
+            nnue::ACCUMULATOR = board_copy.accumulator;
+        }
     }
 }
 
@@ -1692,7 +2148,96 @@ fn parse_fen(fen: &str, char_pieces: &HashMap<char, u32>) {
 
         // init the position hash key
         HASH_KEY = generate_hash_key();
+        PAWN_KEY = generate_pawn_key();
+
+        // init incremental evaluation
+        EVAL_SCORE = compute_eval_score();
+        PHASE_SCORE = get_game_phase_score();
+    }
+
+    #[cfg(feature = "nnue")]
+    nnue::refresh_accumulator();
+}
+
+// check the fields of a FEN before parsing it (parse_fen panics on malformed input)
+fn validate_fen(fen: &str) -> Result<(), String> {
+    let fields = fen.split_whitespace().collect::<Vec<&str>>();
+
+    if fields.len() < 4 || fields.len() > 6 {
+        return Err(format!("expected 4 to 6 fields, got {}", fields.len()));
+    }
+
+    let ranks = fields[0].split('/').collect::<Vec<&str>>();
+
+    if ranks.len() != 8 {
+        return Err(format!("expected 8 ranks, got {}", ranks.len()));
+    }
+
+    for (index, rank) in ranks.iter().enumerate() {
+        let mut files = 0;
+
+        for c in rank.chars() {
+            match c {
+                '1'..='8' => files += c as usize - '0' as usize,
+                'P' | 'p' if index == 0 || index == 7 => return Err(String::from("pawn on the first or last rank")),
+                'P' | 'N' | 'B' | 'R' | 'Q' | 'K' | 'p' | 'n' | 'b' | 'r' | 'q' | 'k' => files += 1,
+                _ => return Err(format!("invalid piece '{}'", c)),
+            }
+        }
+
+        if files != 8 {
+            return Err(format!("rank {} has {} squares", 8 - index, files));
+        }
+    }
+
+    for king in ['K', 'k'] {
+        if fields[0].matches(king).count() != 1 {
+            return Err(format!("expected one '{}'", king));
+        }
+    }
+
+    if fields[1] != "w" && fields[1] != "b" {
+        return Err(format!("invalid side to move '{}'", fields[1]));
+    }
+
+    let castling = fields[2];
+
+    if castling != "-" && (!castling.chars().all(|c| "KQkq".contains(c)) || castling.chars().enumerate().any(|(index, c)| castling[..index].contains(c))) {
+        return Err(format!("invalid castling rights '{}'", castling));
+    }
+
+    let enpassant = fields[3].as_bytes();
+
+    if fields[3] != "-" && !(enpassant.len() == 2 && (b'a'..=b'h').contains(&enpassant[0]) && (enpassant[1] == b'3' || enpassant[1] == b'6')) {
+        return Err(format!("invalid enpassant square '{}'", fields[3]));
+    }
+
+    if fields[4..].iter().any(|counter| counter.parse::<u32>().is_err()) {
+        return Err(String::from("invalid move counters"));
+    }
+
+    Ok(())
+}
+
+// parse a FEN that may be malformed (user input), the side not to move can't be in check
+fn try_parse_fen(fen: &str, char_pieces: &HashMap<char, u32>) -> Result<(), String> {
+    validate_fen(fen).map_err(|e| format!("invalid fen '{}': {}", fen, e))?;
+
+    // single spaces between fields
+    parse_fen(&fen.split_whitespace().collect::<Vec<&str>>().join(" "), char_pieces);
+
+    let king_in_check = unsafe {
+        let enemy = SIDE as usize ^ 1;
+        let king = PIECE_BITBOARDS[enemy * 6 + Piece::K as usize].trailing_zeros() as u64;
+
+        is_square_attacked(king, SIDE as u64)
+    };
+
+    if king_in_check {
+        return Err(format!("invalid fen '{}': the side not to move is in check", fen));
     }
+
+    Ok(())
 }
 
 // Generate pawn attacks
@@ -2343,7 +2888,7 @@ fn generate_moves() -> Vec<u64>{
                                     Ok(val) => val as u64,
                                     Err(_) => panic!()
                                 };
-                                if !is_piece_pinned_absolute(source_square, target_enpassant, SIDE as u64) {
+                                if !is_enpassant_pinned(source_square, target_enpassant, SIDE as u64) {
                                     move_list.push(encode_move!(source_square, target_enpassant, piece as u64, 0, 1, 0, 1, 0));
                                 }
                             }
@@ -2459,7 +3004,7 @@ fn generate_moves() -> Vec<u64>{
                                     Err(_) => panic!()
                                 };
 
-                                if !is_piece_pinned_absolute(source_square, target_enpassant, SIDE as u64) {
+                                if !is_enpassant_pinned(source_square, target_enpassant, SIDE as u64) {
                                     move_list.push(encode_move!(source_square, target_enpassant, piece as u64, 0, 1, 0, 1, 0));
                                 }
                                 
@@ -2828,6 +3373,10 @@ fn make_move(ch_move: u64, move_flag: MOVE_TYPE) -> bool {
             reset_bit!(PIECE_BITBOARDS[piece as usize], source_square);
             set_bit!(PIECE_BITBOARDS[piece as usize], target_square);
 
+            // update material & positional scores
+            remove_piece_score(piece as usize, source_square as usize);
+            add_piece_score(piece as usize, target_square as usize);
+
             // hash piece
             HASH_KEY ^= PIECE_KEYS[piece as usize][source_square as usize]; // remove piece form source square 
             HASH_KEY ^= PIECE_KEYS[piece as usize][target_square as usize]; // set piece on target square
@@ -2852,6 +3401,8 @@ fn make_move(ch_move: u64, move_flag: MOVE_TYPE) -> bool {
                         reset_bit!(PIECE_BITBOARDS[bb_piece], target_square);
                         // remove piece from hash key
                         HASH_KEY ^= PIECE_KEYS[bb_piece][target_square as usize];
+                        // remove captured piece score
+                        remove_piece_score(bb_piece, target_square as usize);
                         break;
                     }
                 } 
@@ -2862,14 +3413,17 @@ fn make_move(ch_move: u64, move_flag: MOVE_TYPE) -> bool {
                     reset_bit!(PIECE_BITBOARDS[Piece::P as usize], target_square);
                     // update hash key
                     HASH_KEY ^= PIECE_KEYS[Piece::P as usize][target_square as usize];
+                    remove_piece_score(Piece::P as usize, target_square as usize);
                 }else{
                     reset_bit!(PIECE_BITBOARDS[Piece::p as usize], target_square);
                     // update hash key
                     HASH_KEY ^= PIECE_KEYS[Piece::p as usize][target_square as usize];
+                    remove_piece_score(Piece::p as usize, target_square as usize);
                 }
                 set_bit!(PIECE_BITBOARDS[promoted as usize], target_square);
                 // update hash key
                 HASH_KEY ^= PIECE_KEYS[promoted as usize][target_square as usize];
+                add_piece_score(promoted as usize, target_square as usize);
                 
             }
 
@@ -2880,11 +3434,13 @@ fn make_move(ch_move: u64, move_flag: MOVE_TYPE) -> bool {
                     reset_bit!(PIECE_BITBOARDS[Piece::p as usize], target_square-8);
                     // remove pawn from hash key
                     HASH_KEY ^= PIECE_KEYS[Piece::p as usize][(target_square-8) as usize];
+                    remove_piece_score(Piece::p as usize, (target_square-8) as usize);
 
                 }else {
                     reset_bit!(PIECE_BITBOARDS[Piece::P as usize], target_square+8);
                     // remove pawn from hash key
                     HASH_KEY ^= PIECE_KEYS[Piece::P as usize][(target_square+8) as usize];
+                    remove_piece_score(Piece::P as usize, (target_square+8) as usize);
                 }
             }
 
@@ -2926,6 +3482,10 @@ fn make_move(ch_move: u64, move_flag: MOVE_TYPE) -> bool {
                         // update hash key
                         HASH_KEY ^= PIECE_KEYS[Piece::R as usize][BoardSquare::h1 as usize];
                         HASH_KEY ^= PIECE_KEYS[Piece::R as usize][BoardSquare::f1 as usize];
+
+                        // update material & positional scores
+                        remove_piece_score(Piece::R as usize, BoardSquare::h1 as usize);
+                        add_piece_score(Piece::R as usize, BoardSquare::f1 as usize);
                     },
 
                     // white castles queen side
@@ -2938,6 +3498,10 @@ fn make_move(ch_move: u64, move_flag: MOVE_TYPE) -> bool {
                         // update hash key
                         HASH_KEY ^= PIECE_KEYS[Piece::R as usize][BoardSquare::a1 as usize];
                         HASH_KEY ^= PIECE_KEYS[Piece::R as usize][BoardSquare::d1 as usize];
+
+                        // update material & positional scores
+                        remove_piece_score(Piece::R as usize, BoardSquare::a1 as usize);
+                        add_piece_score(Piece::R as usize, BoardSquare::d1 as usize);
                     },
 
                     // black castles king side
@@ -2950,6 +3514,10 @@ fn make_move(ch_move: u64, move_flag: MOVE_TYPE) -> bool {
                         // update hash key
                         HASH_KEY ^= PIECE_KEYS[Piece::r as usize][BoardSquare::h8 as usize];
                         HASH_KEY ^= PIECE_KEYS[Piece::r as usize][BoardSquare::f8 as usize];
+
+                        // update material & positional scores
+                        remove_piece_score(Piece::r as usize, BoardSquare::h8 as usize);
+                        add_piece_score(Piece::r as usize, BoardSquare::f8 as usize);
                     },
 
                     // black castles queen side
@@ -2962,6 +3530,10 @@ fn make_move(ch_move: u64, move_flag: MOVE_TYPE) -> bool {
                         // update hash key
                         HASH_KEY ^= PIECE_KEYS[Piece::r as usize][BoardSquare::a8 as usize];
                         HASH_KEY ^= PIECE_KEYS[Piece::r as usize][BoardSquare::d8 as usize];
+
+                        // update material & positional scores
+                        remove_piece_score(Piece::r as usize, BoardSquare::a8 as usize);
+                        add_piece_score(Piece::r as usize, BoardSquare::d8 as usize);
                     },
 
                     _ => {}
@@ -3038,7 +3610,7 @@ fn perft_driver(depth: u64, root: bool) -> usize {
             cnt = 1;
             nodes += 1
         }else {
-            let (piece_bitboards_copy, occupancies_copy, side_copy, enpassant_copy, castle_copy, hash_key_copy) = copy_board();
+            let board_copy = copy_board();
             make_move(mv, MOVE_TYPE::all_moves);
             if leaf {
                 
@@ -3050,11 +3622,11 @@ fn perft_driver(depth: u64, root: bool) -> usize {
 
             nodes += cnt;
             
-            take_back(piece_bitboards_copy, occupancies_copy, side_copy, enpassant_copy, castle_copy, hash_key_copy);
+            take_back(board_copy);
         }
 
         if root {
-            println!("{}{}: {}", SQUARE_TO_COORD[get_move_source!(mv) as usize], SQUARE_TO_COORD[get_move_target!(mv) as usize], cnt);
+            println!("{}: {}", get_uci_move(mv), cnt);
         }
     }
 
@@ -3086,6 +3658,29 @@ fn get_time_ms() -> u64 {
 }
 
 
+// same as is_piece_pinned_absolute but for enpassant captures, the captured
+// pawn leaves its square too (e.g. king & rook on the rank of both pawns)
+fn is_enpassant_pinned(pawn_source_square: u64, enpassant_square: u64, pawn_side: u64) -> bool {
+    unsafe {
+        let (captured_square, captured_pawn) = if pawn_side == PieceColor::WHITE as u64 {
+            (enpassant_square - 8, Piece::p as usize)
+        }else {
+            (enpassant_square + 8, Piece::P as usize)
+        };
+
+        // lift the captured pawn off the board while testing
+        reset_bit!(OCCUPANCIES[PieceColor::BOTH as usize], captured_square);
+        reset_bit!(PIECE_BITBOARDS[captured_pawn], captured_square);
+
+        let pinned = is_piece_pinned_absolute(pawn_source_square, enpassant_square, pawn_side);
+
+        set_bit!(OCCUPANCIES[PieceColor::BOTH as usize], captured_square);
+        set_bit!(PIECE_BITBOARDS[captured_pawn], captured_square);
+
+        pinned
+    }
+}
+
 fn is_piece_pinned_absolute(piece_source_square: u64, piece_target_square:u64, piece_side: u64) -> bool {
     unsafe {
             // update occupancy bitboard with pinned piece move
@@ -3382,16 +3977,20 @@ fn search_position(depth: usize, ht: &mut HashMap<u64, TTEntry>) {
     // clear helper data structures for search
     unsafe {
         NODES = 0;
-        KILLER_MOVES = [[0; 64]; 2];
-        HISTORY_MOVES = [[0; 64]; 12];
+        KILLER_MOVES = [[0; MAX_PLY]; 2];
+        MOVE_STACK = [0; MAX_PLY];
         
-        PV_TABLE = [[0; 64]; 64];
-        PV_LENGTH = [0; 64];
+        PV_TABLE = [[0; MAX_PLY]; MAX_PLY];
+        PREV_PV_TABLE = [[0; MAX_PLY]; MAX_PLY];
+        PV_LENGTH = [0; MAX_PLY];
         FOLLOW_PV = 0;
         SCORE_PV = 0;
         STOPPED = 0;
     }
 
+    #[cfg(feature = "stats")]
+    stats::clear();
+
     // define initial alpha beta bounds
     let mut alpha = -INFINITY ;
     let mut beta = INFINITY;
@@ -3414,6 +4013,20 @@ fn search_position(depth: usize, ht: &mut HashMap<u64, TTEntry>) {
 
         score = negamax(alpha, beta, current_depth, ht);
 
+        // searched with an aspiration window
+        #[cfg(feature = "stats")]
+        if alpha != -INFINITY && unsafe { STOPPED == 0 } {
+            unsafe {
+                stat!(aspiration_searches);
+
+                if score <= alpha {
+                    stat!(aspiration_fails_low);
+                }else if score >= beta {
+                    stat!(aspiration_fails_high);
+                }
+            }
+        }
+
         if (score <= alpha) || (score >= beta) {
             alpha = -INFINITY ;
             beta = INFINITY;
@@ -3424,8 +4037,11 @@ fn search_position(depth: usize, ht: &mut HashMap<u64, TTEntry>) {
         beta = score + 50;
 
         unsafe {
+            if STOPPED == 0 {
+                BEST_SCORE = score;
+            }
             
-            if PV_LENGTH[0] != 0 {
+            if PV_LENGTH[0] != 0 && SEARCH_OUTPUT {
                 if score > -MATE_VALUE  && score < -MATE_SCORE  {
                     print!("info score mate {} depth {} nodes {} pv ", -(score + MATE_VALUE ) / 2 - 1, current_depth, NODES);
                 }else if score > MATE_SCORE  && score < MATE_VALUE  {
@@ -3451,40 +4067,21 @@ fn search_position(depth: usize, ht: &mut HashMap<u64, TTEntry>) {
     }
     
     unsafe {
-        if SEARCH_COMPLETE {
-            BEST_MOVE = PV_TABLE[0][0];
-            if get_move_promoted!(BEST_MOVE) != 0 {
-                println!("bestmove {}{}{}",
-                SQUARE_TO_COORD[get_move_source!(BEST_MOVE) as usize],
-                SQUARE_TO_COORD[get_move_target!(BEST_MOVE) as usize],
-                ASCII_PIECES[get_move_promoted!(BEST_MOVE) as usize]
-            );
-            }else {
-                println!("bestmove {}{}",
-                SQUARE_TO_COORD[get_move_source!(BEST_MOVE) as usize],
-                SQUARE_TO_COORD[get_move_target!(BEST_MOVE) as usize],
-            );
-            }
-        }else {
-            BEST_MOVE = PREV_PV_TABLE[0][0];
-            if get_move_promoted!(BEST_MOVE) != 0 {
-                println!("bestmove {}{}{}",
-                SQUARE_TO_COORD[get_move_source!(BEST_MOVE) as usize],
-                SQUARE_TO_COORD[get_move_target!(BEST_MOVE) as usize],
-                ASCII_PIECES[get_move_promoted!(BEST_MOVE) as usize]
-            );
-            }else {
-                println!("bestmove {}{}",
-                SQUARE_TO_COORD[get_move_source!(BEST_MOVE) as usize],
-                SQUARE_TO_COORD[get_move_target!(BEST_MOVE) as usize],
-            );
-            }
+        BEST_MOVE = if SEARCH_COMPLETE { PV_TABLE[0][0] } else { PREV_PV_TABLE[0][0] };
+
+        #[cfg(feature = "stats")]
+        if SEARCH_OUTPUT {
+            stats::print();
+        }
+
+        // promotions are written in lowercase (e7e8q)
+        if SEARCH_OUTPUT {
+            println!("bestmove {}", get_uci_move(BEST_MOVE));
         }
-        
     }
 }
 
-fn score_move(mv: u64) -> usize {
+fn score_move(mv: u64) -> i32 {
     unsafe {
         // if PV move scoring is enabled 
         if SCORE_PV != 0 {
@@ -3492,74 +4089,143 @@ fn score_move(mv: u64) -> usize {
             if PV_TABLE[0][PLY] == mv {
                 SCORE_PV = 0;
 
-                return 20000;
+                return 3000000;
             }
         }
 
         if get_move_capture!(mv) != 0 {
 
-            let mut target_piece = 0;
-            let mut start_piece = 0;
-            let mut end_piece = 0;
-
-            if SIDE == PieceColor::WHITE as i32 {
-                start_piece = Piece::p as usize;
-                end_piece = Piece::k as usize;
-            }else {
-                start_piece = Piece::P as usize;
-                end_piece = Piece::K as usize;
-            }
-
-            for bb_piece in start_piece..=end_piece {
-                if get_bit!(PIECE_BITBOARDS[bb_piece], get_move_target!(mv)) != 0 {
-                    target_piece = bb_piece;
-                    break;
-                }
-            }
-
-            return MVV_LVA[get_move_piece!(mv) as usize][target_piece] +10000;
+            let target_piece = get_captured_piece(mv);
 
+            1000000 + MVV_LVA[get_move_piece!(mv) as usize][target_piece] as i32 * 100
+                + CAPTURE_HISTORY[get_move_piece!(mv) as usize][get_move_target!(mv) as usize][target_piece] / 8
 
         }else {
             // score 1st killer move
             if KILLER_MOVES[0][PLY] == mv as usize {
-                return 9000;
+                900000
             // score 2nd killer move
             }else if KILLER_MOVES[1][PLY] == mv as usize {
-                return 8000;
+                800000
+            // score counter move
+            }else if PLY > 0 && MOVE_STACK[PLY - 1] != 0
+            && COUNTER_MOVES[get_move_piece!(MOVE_STACK[PLY - 1]) as usize][get_move_target!(MOVE_STACK[PLY - 1]) as usize] == mv {
+                700000
             // score history move
             }else {
-                return HISTORY_MOVES[get_move_piece!(mv) as usize][get_move_target!(mv) as usize];
+                quiet_history(mv)
             }
         }
     }
     
 }
 
-fn is_repition() -> bool {
+// get piece captured by a given capture move
+fn get_captured_piece(mv: u64) -> usize {
     unsafe {
-        // loop over repetition indicies range
-        for index in 0..REPETITION_INDEX {
-            // if we find the current position 
-            if REPETITION_TABLE[index] == HASH_KEY {
-                return true;
-            }
+        let (start_piece, end_piece) = if SIDE == PieceColor::WHITE as i32 {
+            (Piece::p as usize, Piece::k as usize)
+        }else {
+            (Piece::P as usize, Piece::K as usize)
+        };
 
-        }
-        return false;
+        // an enpassant capture finds no piece on the target square
+        (start_piece..=end_piece)
+            .find(|&bb_piece| get_bit!(PIECE_BITBOARDS[bb_piece], get_move_target!(mv)) != 0)
+            .unwrap_or(start_piece)
     }
 }
 
-
-fn enable_pv_scoring(move_list: &Vec<u64>) {
+// main history + continuation histories of a quiet move
+fn quiet_history(mv: u64) -> i32 {
     unsafe {
-        // disable following PV
-        FOLLOW_PV = 0;
+        let piece = get_move_piece!(mv) as usize;
+        let target = get_move_target!(mv) as usize;
 
-        for mv in move_list {
-            // make sure we hit PV move
-            if PV_TABLE[0][PLY] == *mv {
-                // enable move scoring
+        let mut history = HISTORY_MOVES[piece][target];
+
+        // one & two plies back continuation histories
+        for back in 1..=2 {
+            if PLY >= back && MOVE_STACK[PLY - back] != 0 {
+                let previous = MOVE_STACK[PLY - back];
+                history += CONTINUATION_HISTORY[get_move_piece!(previous) as usize][get_move_target!(previous) as usize][piece][target];
+            }
+        }
+
+        history
+    }
+}
+
+// apply bonus (malus) to a history score
+// the closer the score is to HISTORY_MAX the smaller the effect (gravity)
+fn history_gravity(history: i32, bonus: i32) -> i32 {
+    history + bonus - history * bonus.abs() / HISTORY_MAX
+}
+
+// update main history & continuation histories of a quiet move
+fn update_quiet_histories(mv: u64, bonus: i32) {
+    unsafe {
+        let piece = get_move_piece!(mv) as usize;
+        let target = get_move_target!(mv) as usize;
+
+        HISTORY_MOVES[piece][target] = history_gravity(HISTORY_MOVES[piece][target], bonus);
+
+        for back in 1..=2 {
+            if PLY >= back && MOVE_STACK[PLY - back] != 0 {
+                let previous = MOVE_STACK[PLY - back];
+                let (previous_piece, previous_target) = (get_move_piece!(previous) as usize, get_move_target!(previous) as usize);
+                CONTINUATION_HISTORY[previous_piece][previous_target][piece][target] =
+                    history_gravity(CONTINUATION_HISTORY[previous_piece][previous_target][piece][target], bonus);
+            }
+        }
+    }
+}
+
+// update capture history of a capture move
+fn update_capture_history(mv: u64, captured_piece: usize, bonus: i32) {
+    unsafe {
+        let piece = get_move_piece!(mv) as usize;
+        let target = get_move_target!(mv) as usize;
+
+        CAPTURE_HISTORY[piece][target][captured_piece] = history_gravity(CAPTURE_HISTORY[piece][target][captured_piece], bonus);
+    }
+}
+
+// clear move ordering heuristics (new game)
+fn clear_history() {
+    unsafe {
+        KILLER_MOVES = [[0; MAX_PLY]; 2];
+        HISTORY_MOVES = [[0; 64]; 12];
+        COUNTER_MOVES = [[0; 64]; 12];
+        CONTINUATION_HISTORY = [[[[0; 64]; 12]; 64]; 12];
+        CAPTURE_HISTORY = [[[0; 12]; 64]; 12];
+    }
+}
+
+fn is_repition() -> bool {
+    unsafe {
+        // loop over repetition indicies range
+        for index in 0..REPETITION_INDEX {
+            // if we find the current position 
+            if REPETITION_TABLE[index] == HASH_KEY {
+                return true;
+            }
+
+        }
+        return false;
+    }
+}
+
+
+fn enable_pv_scoring(move_list: &Vec<u64>) {
+    unsafe {
+        // disable following PV
+        FOLLOW_PV = 0;
+
+        for mv in move_list {
+            // make sure we hit PV move
+            if PV_TABLE[0][PLY] == *mv {
+                // enable move scoring
                 SCORE_PV = 1;
 
                 // enable following PV
@@ -3602,230 +4268,326 @@ fn get_game_phase_score() -> i32 {
     
 }
 
-fn evaluate() -> i32 {
-    let game_phase_score = get_game_phase_score();
+// material & positional scores [piece][square] (white pieces positive)
+static mut PIECE_SQUARE_SCORES: [[Score; 64]; 12] = [[Score(0); 64]; 12];
 
-    let game_phase = 
-    if game_phase_score > OPENING_PHASE_SCORE {
-        GamePhase::OPENING
-    }else if game_phase_score < ENDGAME_PHASE_SCORE {
-        GamePhase::ENDGAME
-    }else {
-        GamePhase::MIDDLEGAME
-    };
+// init packed material & positional scores
+fn init_piece_square_scores() {
+    unsafe {
+        for piece in Piece::P as usize..=Piece::K as usize {
+            for square in 0..64 {
+                let opening = EVAL_PARAMS.material[GamePhase::OPENING as usize][piece] + EVAL_PARAMS.positional[GamePhase::OPENING as usize][piece][square];
+                let endgame = EVAL_PARAMS.material[GamePhase::ENDGAME as usize][piece] + EVAL_PARAMS.positional[GamePhase::ENDGAME as usize][piece][square];
+
+                // white piece
+                PIECE_SQUARE_SCORES[piece][square] = Score::new(opening, endgame);
+                // black piece on the mirrored square
+                PIECE_SQUARE_SCORES[piece + 6][MIRROR_SCORE[square]] = -Score::new(opening, endgame);
+            }
+        }
+    }
+}
 
-    let mut score: i32 = 0;
+// game phase contribution of a piece (pawns & kings don't count, phase weights are fixed)
+fn piece_phase_score(piece: usize) -> i32 {
+    match piece % 6 {
+        1..=4 => MATERIAL_SCORE_TP[GamePhase::OPENING as usize][piece].abs(),
+        _ => 0,
+    }
+}
 
-    let mut score_opening:i32 = 0;
+// update incremental evaluation when a piece is put on a square
+fn add_piece_score(piece: usize, square: usize) {
+    unsafe {
+        EVAL_SCORE += PIECE_SQUARE_SCORES[piece][square];
+        PHASE_SCORE += piece_phase_score(piece);
 
-    let mut score_endgame: i32 = 0;
+        if piece % 6 == Piece::P as usize {
+            PAWN_KEY ^= PIECE_KEYS[piece][square];
+        }
+    }
 
-    let mut bitboard: u64 = 0;
+    #[cfg(feature = "nnue")]
+    nnue::add_feature(piece, square);
+}
 
-    //let mut piece = 0;
+// update incremental evaluation when a piece is removed from a square
+fn remove_piece_score(piece: usize, square: usize) {
+    unsafe {
+        EVAL_SCORE -= PIECE_SQUARE_SCORES[piece][square];
+        PHASE_SCORE -= piece_phase_score(piece);
 
-    let mut square = 0;
+        if piece % 6 == Piece::P as usize {
+            PAWN_KEY ^= PIECE_KEYS[piece][square];
+        }
+    }
 
-    let mut double_pawns: i32 = 0;
+    #[cfg(feature = "nnue")]
+    nnue::remove_feature(piece, square);
+}
 
-    for bb_piece in Piece::P as usize..=Piece::k as usize {
-        unsafe {
-            bitboard = PIECE_BITBOARDS[bb_piece];
+// piece names used by the evaluation parameter file [piece]
+static PIECE_NAMES: [&str; 6] = ["pawn", "knight", "bishop", "rook", "queen", "king"];
 
-            while bitboard != 0 {
-                //piece = bb_piece;
+/*
+    Evaluation parameter file: one weight per line as `name value...`,
+    scores are written as opening/endgame pairs, piece square tables
+    as 64 values in square order & lines starting with '#' are comments.
+    Weights missing from a file keep their default value.
+*/
+impl EvalParams {
+    // visit every weight by name as a list of values
+    fn visit(&mut self, visitor: &mut dyn FnMut(&str, &mut [i32])) {
+        for phase in [GamePhase::OPENING, GamePhase::ENDGAME] {
+            let phase_name = if phase == GamePhase::OPENING { "opening" } else { "endgame" };
+            let phase = phase as usize;
+
+            // white material (king value stays fixed), black mirrors it
+            let mut material = self.material[phase][..5].to_vec();
+            visitor(&format!("material_{}", phase_name), &mut material);
+
+            for (piece, &value) in material.iter().enumerate() {
+                self.material[phase][piece] = value;
+                self.material[phase][piece + 6] = -value;
+            }
 
-                square = match index_lsb(bitboard) {
-                    Ok(val) => val,
-                    Err(_) => panic!(),
-                };
+            for (piece_name, positional) in PIECE_NAMES.iter().zip(self.positional[phase].iter_mut()) {
+                visitor(&format!("pst_{}_{}", phase_name, piece_name), positional);
+            }
+        }
 
-                score_opening += MATERIAL_SCORE_TP[GamePhase::OPENING as usize][bb_piece];
-                score_endgame += MATERIAL_SCORE_TP[GamePhase::ENDGAME as usize][bb_piece];
+        let mut visit_scores = |name: &str, scores: &mut [Score]| {
+            let mut values = scores.iter().flat_map(|score| [score.opening(), score.endgame()]).collect::<Vec<i32>>();
 
+            visitor(name, &mut values);
 
-                // score positional piece scores
-                match bb_piece {
-                    // evaluate white pieces
-                    0 => {
+            for (index, score) in scores.iter_mut().enumerate() {
+                *score = Score::new(values[index * 2], values[index * 2 + 1]);
+            }
+        };
 
-                        score_opening += POSITIONAL_SCORE[GamePhase::OPENING as usize][Piece::P as usize][square];
-                        score_endgame += POSITIONAL_SCORE[GamePhase::ENDGAME as usize][Piece::P as usize][square];
+        visit_scores("double_pawn_penalty", std::slice::from_mut(&mut self.double_pawn_penalty));
+        visit_scores("isolated_pawn_penalty", std::slice::from_mut(&mut self.isolated_pawn_penalty));
+        visit_scores("passed_pawn_bonus", &mut self.passed_pawn_bonus);
+        visit_scores("passed_pawn_enemy_king_distance", std::slice::from_mut(&mut self.passed_pawn_enemy_king_distance));
+        visit_scores("passed_pawn_own_king_distance", std::slice::from_mut(&mut self.passed_pawn_own_king_distance));
+        visit_scores("passed_pawn_blocked", std::slice::from_mut(&mut self.passed_pawn_blocked));
+        visit_scores("semi_open_file_score", std::slice::from_mut(&mut self.semi_open_file_score));
+        visit_scores("open_file_score", std::slice::from_mut(&mut self.open_file_score));
+        visit_scores("rook_on_seventh", std::slice::from_mut(&mut self.rook_on_seventh));
+        visit_scores("knight_mobility", std::slice::from_mut(&mut self.knight_mobility));
+        visit_scores("bishop_mobility", std::slice::from_mut(&mut self.bishop_mobility));
+        visit_scores("rook_mobility", std::slice::from_mut(&mut self.rook_mobility));
+        visit_scores("queen_mobility", std::slice::from_mut(&mut self.queen_mobility));
+        visit_scores("bishop_pair", std::slice::from_mut(&mut self.bishop_pair));
+        visit_scores("knight_outpost", std::slice::from_mut(&mut self.knight_outpost));
+        visit_scores("king_shield_bonus", std::slice::from_mut(&mut self.king_shield_bonus));
+        visit_scores("king_attack", std::slice::from_mut(&mut self.king_attack));
+        visit_scores("threat_by_pawn", std::slice::from_mut(&mut self.threat_by_pawn));
+        visit_scores("threat_hanging", std::slice::from_mut(&mut self.threat_hanging));
+
+        visitor("king_attack_weights", &mut self.king_attack_weights);
+    }
 
-                        double_pawns = count_bits(PIECE_BITBOARDS[Piece::P as usize] & FILE_MASKS[square]) as i32;
+    // write parameters in the evaluation parameter file format
+    fn to_text(mut self) -> String {
+        let mut text = String::from("# cheng evaluation parameters\n");
 
-                        if double_pawns > 1 {
-                            score_opening += (double_pawns - 1) * DOUBLE_PAWN_PENALTY_OPENING;
-                            score_endgame += (double_pawns - 1) * DOUBLE_PAWN_PENALTY_ENDGAME;
-                        }
+        self.visit(&mut |name, values| {
+            let values = values.iter().map(|value| value.to_string()).collect::<Vec<String>>();
 
-                        // on isolated pawn
-                        if PIECE_BITBOARDS[Piece::P as usize] & ISOLATED_MASKS[square] == 0 {
-                            score_opening += ISOLATED_PAWN_PENALTY_OPENING;
-                            score_endgame += ISOLATED_PAWN_PENALTY_ENDGAME;
-                        }
+            text.push_str(&format!("{} {}\n", name, values.join(" ")));
+        });
 
-                        // on passed pawn
-                        if WHITE_PASSED_MASKS[square] & PIECE_BITBOARDS[Piece::p as usize] == 0 {
-                            score_opening += WHITE_PASSED_PAWN_BONUS[GET_RANK[square]];
-                            score_endgame += WHITE_PASSED_PAWN_BONUS[GET_RANK[square]];
-                        }
+        text
+    }
 
-                    },
-                    1 => {
-                        score_opening += POSITIONAL_SCORE[GamePhase::OPENING as usize][Piece::N as usize][square];
-                        score_endgame += POSITIONAL_SCORE[GamePhase::ENDGAME as usize][Piece::N as usize][square];
-                    },
-                    2 => {
+    // all weights as a flat list of values
+    fn to_vec(mut self) -> Vec<i32> {
+        let mut weights = Vec::new();
 
-                        score_opening += POSITIONAL_SCORE[GamePhase::OPENING as usize][Piece::B as usize][square];
-                        score_endgame += POSITIONAL_SCORE[GamePhase::ENDGAME as usize][Piece::B as usize][square];
+        self.visit(&mut |_, values| weights.extend_from_slice(values));
 
-                        // mobility
-                        score_opening += (count_bits(get_bishop_attacks(square as u64, OCCUPANCIES[PieceColor::BOTH as usize])) as i32 - BISHOP_UNIT)  * BISHOP_MOBILITY_OPENING;
-                        score_endgame += (count_bits(get_bishop_attacks(square as u64, OCCUPANCIES[PieceColor::BOTH as usize])) as i32 - BISHOP_UNIT) * BISHOP_MOBILITY_ENDGAME;
+        weights
+    }
 
-                    },
-                    3 => {
+    // weights from a flat list of values (same order as to_vec)
+    fn from_vec(weights: &[i32]) -> EvalParams {
+        let mut params = DEFAULT_EVAL_PARAMS;
+        let mut index = 0;
 
-                        score_opening += POSITIONAL_SCORE[GamePhase::OPENING as usize][Piece::R as usize][square];
-                        score_endgame += POSITIONAL_SCORE[GamePhase::ENDGAME as usize][Piece::R as usize][square];
+        params.visit(&mut |_, values| {
+            values.copy_from_slice(&weights[index..index + values.len()]);
+            index += values.len();
+        });
 
-                        // semi open file
-                        if PIECE_BITBOARDS[Piece::P as usize] & FILE_MASKS[square] == 0 {
-                            score_opening += SEMI_OPEN_FILE_SCORE;
-                            score_endgame += SEMI_OPEN_FILE_SCORE;
-                        }
+        params
+    }
 
-                        // open file
-                        if (PIECE_BITBOARDS[Piece::P as usize] | PIECE_BITBOARDS[Piece::p as usize]) & FILE_MASKS[square] == 0 {
-                            score_opening += OPEN_FILE_SCORE;
-                            score_endgame += OPEN_FILE_SCORE;
-                        }
+    // read parameters from the evaluation parameter file format
+    fn from_text(text: &str) -> Result<EvalParams, String> {
+        let mut weights: HashMap<String, Vec<i32>> = HashMap::new();
 
-                    },
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
 
-                    4 => {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
 
-                        score_opening += POSITIONAL_SCORE[GamePhase::OPENING as usize][Piece::Q as usize][square];
-                        score_endgame += POSITIONAL_SCORE[GamePhase::ENDGAME as usize][Piece::Q as usize][square];
+            let mut tokens = line.split_whitespace();
+            let name = tokens.next().unwrap_or_default().to_string();
 
-                        //mobility
-                        score_opening += (count_bits(get_queen_attacks(square as u64, OCCUPANCIES[PieceColor::BOTH as usize])) as i32 - QUEEN_UNIT) * QUEEN_MOBILITY_OPENING;
-                        score_endgame += (count_bits(get_queen_attacks(square as u64, OCCUPANCIES[PieceColor::BOTH as usize])) as i32 - QUEEN_UNIT) * QUEEN_MOBILITY_ENDGAME;
+            let values = tokens
+                .map(|token| token.parse::<i32>().map_err(|e| format!("line {}: bad value '{}' ({})", line_number + 1, token, e)))
+                .collect::<Result<Vec<i32>, String>>()?;
 
-                    },
-                    5 => {
+            weights.insert(name, values);
+        }
 
-                        score_opening += POSITIONAL_SCORE[GamePhase::OPENING as usize][Piece::K as usize][square];
-                        score_endgame += POSITIONAL_SCORE[GamePhase::ENDGAME as usize][Piece::K as usize][square];
+        let mut params = DEFAULT_EVAL_PARAMS;
+        let mut error = None;
 
-                        //semi open file
-                        if PIECE_BITBOARDS[Piece::P as usize] & FILE_MASKS[square] == 0 {
-                            score_opening -= SEMI_OPEN_FILE_SCORE;
-                            score_endgame -= SEMI_OPEN_FILE_SCORE;
-                        }
+        params.visit(&mut |name, values| {
+            if let Some(new_values) = weights.remove(name) {
+                if new_values.len() == values.len() {
+                    values.copy_from_slice(&new_values);
+                }else if error.is_none() {
+                    error = Some(format!("{}: expected {} values, got {}", name, values.len(), new_values.len()));
+                }
+            }
+        });
 
-                        // open file
-                        if (PIECE_BITBOARDS[Piece::P as usize] | PIECE_BITBOARDS[Piece::p as usize]) & FILE_MASKS[square] == 0 {
-                            score_opening -= OPEN_FILE_SCORE;
-                            score_endgame -= OPEN_FILE_SCORE;
-                        }
-                        // king safety bonus
-                        score_opening += count_bits(KING_ATTACKS[square] & OCCUPANCIES[PieceColor::WHITE as usize]) as i32 * KING_SHIELD_BONUS;
-                        score_endgame += count_bits(KING_ATTACKS[square] & OCCUPANCIES[PieceColor::WHITE as usize]) as i32 * KING_SHIELD_BONUS;
-                    },
+        if let Some(error) = error {
+            return Err(error);
+        }
 
-                    // evaluate Black pieces
-                    6 => {
+        if let Some(name) = weights.keys().next() {
+            return Err(format!("unknown parameter '{}'", name));
+        }
 
-                        score_opening -= POSITIONAL_SCORE[GamePhase::OPENING as usize][Piece::P as usize][MIRROR_SCORE[square]];
-                        score_endgame -= POSITIONAL_SCORE[GamePhase::ENDGAME as usize][Piece::P as usize][MIRROR_SCORE[square]];
+        Ok(params)
+    }
+}
 
-                        double_pawns = count_bits(PIECE_BITBOARDS[Piece::p as usize] & FILE_MASKS[square]) as i32;
+// use new evaluation parameters
+fn set_eval_params(params: EvalParams) {
+    unsafe {
+        EVAL_PARAMS = params;
+    }
 
-                        if double_pawns > 1 {
-                            score_opening -= (double_pawns - 1) * DOUBLE_PAWN_PENALTY_OPENING;
-                            score_endgame -= (double_pawns - 1) * DOUBLE_PAWN_PENALTY_ENDGAME;
-                        }
+    // rebuild piece square scores & the incremental score of the current position
+    init_piece_square_scores();
+    clear_pawn_hash();
 
-                        // on isolated pawn
-                        if PIECE_BITBOARDS[Piece::p as usize] & ISOLATED_MASKS[square] == 0 {
-                            score_opening -= ISOLATED_PAWN_PENALTY_OPENING;
-                            score_endgame -= ISOLATED_PAWN_PENALTY_ENDGAME;
-                        }
+    unsafe {
+        EVAL_SCORE = compute_eval_score();
+    }
+}
 
-                        // on passed pawn
-                        if BLACK_PASSED_MASKS[square] & PIECE_BITBOARDS[Piece::P as usize] == 0 {
-                            score_opening -= BLACK_PASSED_PAWN_BONUS[GET_RANK[square]];
-                            score_endgame -= BLACK_PASSED_PAWN_BONUS[GET_RANK[square]];
-                        }
+// load evaluation parameters from a file
+fn load_eval_params(path: &str) -> Result<(), String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
 
+    set_eval_params(EvalParams::from_text(&text)?);
 
-                    },
-                    7 => {
+    Ok(())
+}
 
-                        score_opening -= POSITIONAL_SCORE[GamePhase::OPENING as usize][Piece::N as usize][MIRROR_SCORE[square]];
-                        score_endgame -= POSITIONAL_SCORE[GamePhase::ENDGAME as usize][Piece::N as usize][MIRROR_SCORE[square]];
-                    },
-                    8 => {
+// load an evaluation file: nnue network (*.nnue) or evaluation parameters
+fn load_eval_file(path: &str) -> Result<(), String> {
+    #[cfg(feature = "nnue")]
+    if path.ends_with(".nnue") {
+        return nnue::load_network(path);
+    }
 
-                        score_opening -= POSITIONAL_SCORE[GamePhase::OPENING as usize][Piece::B as usize][MIRROR_SCORE[square]];
-                        score_endgame -= POSITIONAL_SCORE[GamePhase::ENDGAME as usize][Piece::B as usize][MIRROR_SCORE[square]];
+    load_eval_params(path)
+}
 
-                        // mobility
-                        score_opening -= (count_bits(get_bishop_attacks(square as u64, OCCUPANCIES[PieceColor::BOTH as usize])) as i32 - BISHOP_UNIT) * BISHOP_MOBILITY_OPENING;
-                        score_endgame -= (count_bits(get_bishop_attacks(square as u64, OCCUPANCIES[PieceColor::BOTH as usize])) as i32 - BISHOP_UNIT) * BISHOP_MOBILITY_ENDGAME;
-                    },
-                    9 =>  {
-
-                        score_opening -= POSITIONAL_SCORE[GamePhase::OPENING as usize][Piece::R as usize][MIRROR_SCORE[square]];
-                        score_endgame -= POSITIONAL_SCORE[GamePhase::ENDGAME as usize][Piece::R as usize][MIRROR_SCORE[square]];
-                        // semi open file
-                        if PIECE_BITBOARDS[Piece::p as usize] & FILE_MASKS[square] == 0 {
-                            score_opening -= SEMI_OPEN_FILE_SCORE;
-                            score_endgame -= SEMI_OPEN_FILE_SCORE;
-                        }
+// save evaluation parameters in use to a file
+fn save_eval_params(path: &str) -> Result<(), String> {
+    let params = unsafe { EVAL_PARAMS };
 
-                        // open file
-                        if (PIECE_BITBOARDS[Piece::P as usize] | PIECE_BITBOARDS[Piece::p as usize]) & FILE_MASKS[square] == 0 {
-                            score_opening -= OPEN_FILE_SCORE;
-                            score_endgame -= OPEN_FILE_SCORE;
-                        }
+    std::fs::write(path, params.to_text()).map_err(|e| format!("cannot write {}: {}", path, e))
+}
 
-                    },
-                    10 => {
+// compute material & positional score from scratch
+fn compute_eval_score() -> Score {
+    let mut eval_score = Score::default();
 
-                        score_opening -= POSITIONAL_SCORE[GamePhase::OPENING as usize][Piece::Q as usize][MIRROR_SCORE[square]];
-                        score_endgame -= POSITIONAL_SCORE[GamePhase::ENDGAME as usize][Piece::Q as usize][MIRROR_SCORE[square]];
+    for piece in Piece::P as usize..=Piece::k as usize {
+        let mut bitboard = unsafe { PIECE_BITBOARDS[piece] };
 
-                        // mobility
-                        score_opening -= (count_bits(get_queen_attacks(square as u64, OCCUPANCIES[PieceColor::BOTH as usize])) as i32 - QUEEN_UNIT) * QUEEN_MOBILITY_OPENING;
-                        score_endgame -= (count_bits(get_queen_attacks(square as u64, OCCUPANCIES[PieceColor::BOTH as usize])) as i32 - QUEEN_UNIT) * QUEEN_MOBILITY_ENDGAME;
-                    },
-                    11 => {
+        while bitboard != 0 {
+            let square = bitboard.trailing_zeros() as usize;
 
-                        score_opening -= POSITIONAL_SCORE[GamePhase::OPENING as usize][Piece::K as usize][MIRROR_SCORE[square]];
-                        score_endgame -= POSITIONAL_SCORE[GamePhase::ENDGAME as usize][Piece::K as usize][MIRROR_SCORE[square]];
+            eval_score += unsafe { PIECE_SQUARE_SCORES[piece][square] };
 
-                        // semi open file
-                        if PIECE_BITBOARDS[Piece::p as usize] & FILE_MASKS[square] == 0 {
-                            score_opening += SEMI_OPEN_FILE_SCORE;
-                            score_endgame += SEMI_OPEN_FILE_SCORE;
-                        }
+            reset_bit!(bitboard, square);
+        }
+    }
 
-                        // open file
-                        if (PIECE_BITBOARDS[Piece::P as usize] | PIECE_BITBOARDS[Piece::p as usize]) & FILE_MASKS[square] == 0 {
-                            score_opening += OPEN_FILE_SCORE;
-                            score_endgame += OPEN_FILE_SCORE;
-                        }
+    eval_score
+}
 
-                        // king safety bonus
-                        score_opening -= count_bits(KING_ATTACKS[square] & OCCUPANCIES[PieceColor::BLACK as usize]) as i32 * KING_SHIELD_BONUS;
-                        score_endgame -= count_bits(KING_ATTACKS[square] & OCCUPANCIES[PieceColor::BLACK as usize]) as i32 * KING_SHIELD_BONUS;
-                    },
+// pawn structure cache entry
+#[derive(Copy, Clone)]
+struct PawnEntry {
+    key: u64,
+    // doubled & isolated pawns score [side]
+    score: [Score; 2],
+    // passed pawns [side]
+    passed: [u64; 2],
+}
+
+// number of pawn structure cache entries
+const PAWN_HASH_SIZE: usize = 16384;
+
+static EMPTY_PAWN_ENTRY: PawnEntry = PawnEntry { key: 0, score: [Score(0); 2], passed: [0; 2] };
+
+// pawn structure cache [pawn key % size]
+// (an empty entry is also the correct entry of positions without pawns)
+static mut PAWN_HASH_TABLE: [PawnEntry; PAWN_HASH_SIZE] = [EMPTY_PAWN_ENTRY; PAWN_HASH_SIZE];
+
+// clear pawn structure cache (scores depend on evaluation parameters)
+fn clear_pawn_hash() {
+    unsafe {
+        PAWN_HASH_TABLE = [EMPTY_PAWN_ENTRY; PAWN_HASH_SIZE];
+    }
+}
+
+// evaluate pawn structure of both sides from scratch
+fn compute_pawn_entry(pawn_key: u64) -> PawnEntry {
+    let mut pawn_entry = PawnEntry { key: pawn_key, score: [Score::default(); 2], passed: [0; 2] };
 
-                    _ => {},
+    unsafe {
+        for side in [PieceColor::WHITE as usize, PieceColor::BLACK as usize] {
+            let passed_masks = if side == PieceColor::WHITE as usize { &raw const WHITE_PASSED_MASKS } else { &raw const BLACK_PASSED_MASKS };
+            let pawns = PIECE_BITBOARDS[side * 6 + Piece::P as usize];
+            let enemy_pawns = PIECE_BITBOARDS[(side ^ 1) * 6 + Piece::P as usize];
+            let mut bitboard = pawns;
+
+            while bitboard != 0 {
+                let square = match index_lsb(bitboard) {
+                    Ok(val) => val,
+                    Err(e) => panic!("invalid bitboard: {:?}", e),
+                };
+
+                // on doubled pawns
+                let double_pawns = count_bits(pawns & FILE_MASKS[square]) as i32;
+
+                if double_pawns > 1 {
+                    pawn_entry.score[side] += EVAL_PARAMS.double_pawn_penalty * (double_pawns - 1);
+                }
+
+                // on isolated pawn
+                if pawns & ISOLATED_MASKS[square] == 0 {
+                    pawn_entry.score[side] += EVAL_PARAMS.isolated_pawn_penalty;
+                }
+
+                // on passed pawn
+                if (*passed_masks)[square] & enemy_pawns == 0 {
+                    set_bit!(pawn_entry.passed[side], square);
                 }
 
                 reset_bit!(bitboard, square);
@@ -3833,145 +4595,722 @@ fn evaluate() -> i32 {
         }
     }
 
-    /*          
-        Now in order to calculate interpolated score
-        for a given game phase we use this formula
-        (same for material and positional scores):
-        
-        (
-          score_opening * game_phase_score + 
-          score_endgame * (opening_phase_score - game_phase_score)
-        ) / opening_phase_score
+    pawn_entry
+}
 
-        E.g. the score for pawn on d4 at phase say 5000 would be
-        interpolated_score = (12 * 5000 + (-7) * (6192 - 5000)) / 6192 = 8,342377261
-    */
+// pawn structure of the current position from the cache
+fn probe_pawn_entry() -> PawnEntry {
+    let pawn_key = unsafe { PAWN_KEY };
 
-    // interpolate score in the middlegame
-    if game_phase == GamePhase::MIDDLEGAME {
-        score = (
-            score_opening * game_phase_score + 
-            score_endgame * (OPENING_PHASE_SCORE  - game_phase_score)
-        ) / OPENING_PHASE_SCORE
-    }else if game_phase == GamePhase::OPENING {
-        score = score_opening;
-    }else if game_phase == GamePhase::ENDGAME {
-        score = score_endgame;
-    }
+    // incremental pawn key must match the full recomputation
+    debug_assert_eq!(pawn_key, generate_pawn_key(), "incremental pawn key out of sync");
+
+    let index = (pawn_key % PAWN_HASH_SIZE as u64) as usize;
 
     unsafe {
-        if SIDE == PieceColor::WHITE as i32 {
-            score 
-        }else {
-            -score 
+        if PAWN_HASH_TABLE[index].key != pawn_key {
+            PAWN_HASH_TABLE[index] = compute_pawn_entry(pawn_key);
         }
+
+        PAWN_HASH_TABLE[index]
     }
+}
 
+// attack information shared by evaluation terms
+struct EvalInfo {
+    // squares attacked by each side [side]
+    attacks: [u64; 2],
+    // squares attacked by pawns of each side [side]
+    pawn_attacks: [u64; 2],
+    // cached pawn structure
+    pawn_entry: PawnEntry,
 }
 
-fn sort_moves(move_list: &mut Vec<u64>) {
+// squares attacked by a piece on a given square
+fn piece_attacks(piece: usize, square: usize, occupancy: u64) -> u64 {
+    unsafe {
+        match piece {
+            0 => PAWN_ATTACKS[PieceColor::WHITE as usize][square],
+            6 => PAWN_ATTACKS[PieceColor::BLACK as usize][square],
+            1 | 7 => KNIGHT_ATTACKS[square],
+            2 | 8 => get_bishop_attacks(square as u64, occupancy),
+            3 | 9 => get_rook_attacks(square as u64, occupancy),
+            4 | 10 => get_queen_attacks(square as u64, occupancy),
+            _ => KING_ATTACKS[square],
+        }
+    }
+}
 
-    let mut move_scores = move_list.clone().iter().map(|x| score_move(*x)).collect::<Vec<usize>>();
+// rank of a square from the side's point of view
+fn relative_rank(square: usize, side: usize) -> usize {
+    if side == PieceColor::WHITE as usize {
+        GET_RANK[square]
+    }else {
+        7 - GET_RANK[square]
+    }
+}
 
-    for current_move in 0..move_list.len() {
-        for next_move in current_move+1..move_list.len() {
-            if move_scores[current_move] < move_scores[next_move] {
-                move_list.swap(current_move, next_move);
-                move_scores.swap(current_move, next_move);
-            }
+// square of the side's king
+fn king_square(side: usize) -> usize {
+    unsafe {
+        match index_lsb(PIECE_BITBOARDS[side * 6 + Piece::K as usize]) {
+            Ok(val) => val,
+            Err(e) => panic!("error: {:?}", e),
         }
     }
 }
 
-fn quiescence(mut alpha: i32, beta: i32) -> i32 {
+// king distance between squares
+fn square_distance(square_1: usize, square_2: usize) -> i32 {
+    let file_distance = (square_1 as i32 % 8 - square_2 as i32 % 8).abs();
+    let rank_distance = (square_1 as i32 / 8 - square_2 as i32 / 8).abs();
 
-    unsafe{
-    // every 2047 nodes
-    if (NODES & 2047) == 0{
-        communicate();  
-    }
+    file_distance.max(rank_distance)
+}
 
-    // increment nodes count
-    NODES += 1;
+// collect attack maps of both sides
+fn init_eval_info() -> EvalInfo {
+    let mut eval_info = EvalInfo { attacks: [0; 2], pawn_attacks: [0; 2], pawn_entry: probe_pawn_entry() };
 
-    if PLY > 63 {
-        return evaluate();
-    }
+    unsafe {
+        let occupancy = OCCUPANCIES[PieceColor::BOTH as usize];
+        let bitboards = PIECE_BITBOARDS;
 
-    // evaluate position
-    let evaluation = evaluate();
+        for (piece, mut bitboard) in bitboards.into_iter().enumerate() {
+            let side = piece / 6;
 
-    // fail-hard beta cutoff
-    if evaluation >= beta {
-        // node (move) fails high
-        return beta;
-    }
+            while bitboard != 0 {
+                let square = match index_lsb(bitboard) {
+                    Ok(val) => val,
+                    Err(e) => panic!("invalid bitboard: {:?}", e),
+                };
+                let attacks = piece_attacks(piece, square, occupancy);
+
+                eval_info.attacks[side] |= attacks;
+
+                if piece % 6 == Piece::P as usize {
+                    eval_info.pawn_attacks[side] |= attacks;
+                }
 
-    // found a better move
-    if evaluation > alpha {
-        // PV node (move)
-        alpha = evaluation;
+                reset_bit!(bitboard, square);
+            }
+        }
     }
 
-    let mut legal_moves = generate_moves();
+    eval_info
+}
 
-    legal_moves.sort_by_key(|&x|  std::cmp::Reverse(score_move(x)));
+// doubled & isolated pawns
+fn evaluate_pawns(side: usize, eval_info: &EvalInfo) -> Score {
+    eval_info.pawn_entry.score[side]
+}
 
-    //sort_moves(&mut legal_moves);
-    
-    for mv in legal_moves.iter() {
-        // preserve board state
-        let (piece_bitboards_copy, occupancies_copy, side_copy, enpassant_copy, castle_copy, hash_key_copy) = copy_board();
+// passed pawns: rank bonus, king distances & blockade
+fn evaluate_passed_pawns(side: usize, eval_info: &EvalInfo) -> Score {
+    let mut score = Score::default();
 
-        // increment ply
-        PLY += 1;
-        
-        // increment repetition index & store hash key
-        REPETITION_INDEX += 1;
-        REPETITION_TABLE[REPETITION_INDEX] = HASH_KEY;
+    unsafe {
+        let enemy = side ^ 1;
+        let own_king = king_square(side);
+        let enemy_king = king_square(enemy);
 
-        if !make_move(*mv, MOVE_TYPE::only_captures) {
-            PLY -= 1;
-            // decrement repetition index
-            REPETITION_INDEX -= 1;
-            continue;
-        }
+        let mut bitboard = eval_info.pawn_entry.passed[side];
 
-        let score = -quiescence(-beta, -alpha);
+        while bitboard != 0 {
+            let square = match index_lsb(bitboard) {
+                Ok(val) => val,
+                Err(e) => panic!("invalid bitboard: {:?}", e),
+            };
 
-        PLY-= 1;
+            let rank = relative_rank(square, side);
 
-        // decrement repetition index
-        REPETITION_INDEX -= 1;
+            score += EVAL_PARAMS.passed_pawn_bonus[rank];
 
-        take_back(piece_bitboards_copy, occupancies_copy, side_copy, enpassant_copy, castle_copy, hash_key_copy);
+            // square in front of the pawn
+            let stop_square = if side == PieceColor::WHITE as usize { square + 8 } else { square - 8 };
 
-        if STOPPED == 1 {
-            return 0;
-        }
+            if stop_square < 64 && rank < 7 {
+                // kings racing to the pawn's path
+                score += EVAL_PARAMS.passed_pawn_enemy_king_distance * (square_distance(enemy_king, stop_square) * rank as i32);
+                score += EVAL_PARAMS.passed_pawn_own_king_distance * (square_distance(own_king, stop_square) * rank as i32);
 
-        // found a better move
-        if score > alpha {
-            alpha = score;
-            // fail-hard beta cutoff
-            if score >= beta {
-                // node (move) fails high
-                return beta;
-        }
+                // blockaded pawn
+                if get_bit!(OCCUPANCIES[enemy], stop_square) == 1 {
+                    score += EVAL_PARAMS.passed_pawn_blocked;
+                }
+            }
+
+            reset_bit!(bitboard, square);
         }
     }
-    return alpha;
-    }
-    
+
+    score
 }
 
+// knight, bishop, rook & queen mobility
+fn evaluate_mobility(side: usize, _eval_info: &EvalInfo) -> Score {
+    let mut score = Score::default();
 
-// negamax alpha beta search
-fn negamax(mut alpha: i32, beta: i32, mut depth: usize, ht: &mut HashMap<u64, TTEntry>) -> i32 {
     unsafe {
+        let occupancy = OCCUPANCIES[PieceColor::BOTH as usize];
 
-        // init PV length
-        PV_LENGTH[PLY] = PLY as u64;
+        for piece in Piece::N as usize..=Piece::Q as usize {
+            let (mobility, unit) = match piece {
+                1 => (EVAL_PARAMS.knight_mobility, KNIGHT_UNIT),
+                2 => (EVAL_PARAMS.bishop_mobility, BISHOP_UNIT),
+                3 => (EVAL_PARAMS.rook_mobility, ROOK_UNIT),
+                _ => (EVAL_PARAMS.queen_mobility, QUEEN_UNIT),
+            };
+
+            let mut bitboard = PIECE_BITBOARDS[side * 6 + piece];
+
+            while bitboard != 0 {
+                let square = match index_lsb(bitboard) {
+                    Ok(val) => val,
+                    Err(e) => panic!("invalid bitboard: {:?}", e),
+                };
+
+                score += mobility * (count_bits(piece_attacks(piece, square, occupancy)) as i32 - unit);
+
+                reset_bit!(bitboard, square);
+            }
+        }
+    }
+
+    score
+}
+
+// king shield, open files next to the king & attacks on the king zone
+fn evaluate_king_safety(side: usize, _eval_info: &EvalInfo) -> Score {
+    let mut score = Score::default();
+
+    unsafe {
+        let enemy = side ^ 1;
+        let king_square = king_square(side);
+        let all_pawns = PIECE_BITBOARDS[Piece::P as usize] | PIECE_BITBOARDS[Piece::p as usize];
+
+        //semi open file
+        if PIECE_BITBOARDS[side * 6 + Piece::P as usize] & FILE_MASKS[king_square] == 0 {
+            score -= EVAL_PARAMS.semi_open_file_score;
+        }
+
+        // open file
+        if all_pawns & FILE_MASKS[king_square] == 0 {
+            score -= EVAL_PARAMS.open_file_score;
+        }
+
+        // king safety bonus
+        score += EVAL_PARAMS.king_shield_bonus * count_bits(KING_ATTACKS[king_square] & OCCUPANCIES[side]) as i32;
+
+        // king zone attacks
+        let king_zone = KING_ATTACKS[king_square] | (1u64 << king_square);
+        let occupancy = OCCUPANCIES[PieceColor::BOTH as usize];
+
+        let mut attackers = 0;
+        let mut attack_units = 0;
+
+        for piece in Piece::N as usize..=Piece::Q as usize {
+            let mut bitboard = PIECE_BITBOARDS[enemy * 6 + piece];
+
+            while bitboard != 0 {
+                let square = match index_lsb(bitboard) {
+                    Ok(val) => val,
+                    Err(e) => panic!("invalid bitboard: {:?}", e),
+                };
+                let zone_attacks = piece_attacks(piece, square, occupancy) & king_zone;
+
+                if zone_attacks != 0 {
+                    attackers += 1;
+                    attack_units += EVAL_PARAMS.king_attack_weights[piece] * count_bits(zone_attacks) as i32;
+                }
+
+                reset_bit!(bitboard, square);
+            }
+        }
+
+        score += EVAL_PARAMS.king_attack * (attack_units * KING_ATTACKERS_SCALE[attackers.min(7)] / 100);
+    }
+
+    score
+}
+
+// enemy pieces attacked by pawns or hanging
+fn evaluate_threats(side: usize, eval_info: &EvalInfo) -> Score {
+    let mut score = Score::default();
+
+    unsafe {
+        let enemy = side ^ 1;
+
+        // enemy knights, bishops, rooks & queens
+        let enemy_pieces = OCCUPANCIES[enemy] ^ PIECE_BITBOARDS[enemy * 6 + Piece::P as usize] ^ PIECE_BITBOARDS[enemy * 6 + Piece::K as usize];
+
+        // pieces attacked by pawns
+        score += EVAL_PARAMS.threat_by_pawn * count_bits(enemy_pieces & eval_info.pawn_attacks[side]) as i32;
+
+        // attacked pieces (pawns included) nobody defends
+        let enemy_targets = OCCUPANCIES[enemy] ^ PIECE_BITBOARDS[enemy * 6 + Piece::K as usize];
+        score += EVAL_PARAMS.threat_hanging * count_bits(enemy_targets & eval_info.attacks[side] & !eval_info.attacks[enemy]) as i32;
+    }
+
+    score
+}
+
+// bishop pair & knight outposts
+fn evaluate_pieces(side: usize, eval_info: &EvalInfo) -> Score {
+    let mut score = Score::default();
+
+    unsafe {
+        let enemy = side ^ 1;
+
+        // bishop pair
+        if count_bits(PIECE_BITBOARDS[side * 6 + Piece::B as usize]) >= 2 {
+            score += EVAL_PARAMS.bishop_pair;
+        }
+
+        // knight outposts
+        let passed_masks = if side == PieceColor::WHITE as usize { &raw const WHITE_PASSED_MASKS } else { &raw const BLACK_PASSED_MASKS };
+        let enemy_pawns = PIECE_BITBOARDS[enemy * 6 + Piece::P as usize];
+
+        let mut bitboard = PIECE_BITBOARDS[side * 6 + Piece::N as usize];
+
+        while bitboard != 0 {
+            let square = match index_lsb(bitboard) {
+                Ok(val) => val,
+                Err(e) => panic!("invalid bitboard: {:?}", e),
+            };
+            let rank = relative_rank(square, side);
+
+            // on 4th-6th rank, defended by a pawn & no enemy pawn on adjacent files can chase it
+            if (3..=5).contains(&rank)
+            && get_bit!(eval_info.pawn_attacks[side], square) == 1
+            && (*passed_masks)[square] & ISOLATED_MASKS[square] & enemy_pawns == 0
+            {
+                score += EVAL_PARAMS.knight_outpost;
+            }
+
+            reset_bit!(bitboard, square);
+        }
+    }
+
+    score
+}
+
+// rooks on (semi) open files & the seventh rank
+fn evaluate_rooks(side: usize, _eval_info: &EvalInfo) -> Score {
+    let mut score = Score::default();
+
+    unsafe {
+        let enemy = side ^ 1;
+        let all_pawns = PIECE_BITBOARDS[Piece::P as usize] | PIECE_BITBOARDS[Piece::p as usize];
+        let enemy_king = king_square(enemy);
+        let enemy_pawns = PIECE_BITBOARDS[enemy * 6 + Piece::P as usize];
+
+        let mut bitboard = PIECE_BITBOARDS[side * 6 + Piece::R as usize];
+
+        while bitboard != 0 {
+            let square = match index_lsb(bitboard) {
+                Ok(val) => val,
+                Err(e) => panic!("invalid bitboard: {:?}", e),
+            };
+
+            // semi open file
+            if PIECE_BITBOARDS[side * 6 + Piece::P as usize] & FILE_MASKS[square] == 0 {
+                score += EVAL_PARAMS.semi_open_file_score;
+            }
+
+            // open file
+            if all_pawns & FILE_MASKS[square] == 0 {
+                score += EVAL_PARAMS.open_file_score;
+            }
+
+            // rook on seventh
+            if relative_rank(square, side) == 6
+            && (relative_rank(enemy_king, side) == 7 || enemy_pawns & RANK_MASKS[square] != 0)
+            {
+                score += EVAL_PARAMS.rook_on_seventh;
+            }
+
+            reset_bit!(bitboard, square);
+        }
+    }
+
+    score
+}
+
+// name & score of a side for an evaluation term
+type EvalTerm = (&'static str, fn(usize, &EvalInfo) -> Score);
+
+// dynamic evaluation terms added on top of material & positional scores
+static EVAL_TERMS: [EvalTerm; 7] = [
+    ("Pawns", evaluate_pawns),
+    ("Passed pawns", evaluate_passed_pawns),
+    ("Mobility", evaluate_mobility),
+    ("King safety", evaluate_king_safety),
+    ("Threats", evaluate_threats),
+    ("Pieces", evaluate_pieces),
+    ("Rooks", evaluate_rooks),
+];
+
+// material score of a side's pieces
+fn material_score(side: usize) -> Score {
+    let mut score = Score::default();
+
+    for piece in Piece::P as usize..=Piece::K as usize {
+        unsafe {
+            let count = count_bits(PIECE_BITBOARDS[side * 6 + piece]) as i32;
+
+            score += Score::new(EVAL_PARAMS.material[GamePhase::OPENING as usize][piece], EVAL_PARAMS.material[GamePhase::ENDGAME as usize][piece]) * count;
+        }
+    }
+
+    score
+}
+
+// piece square table score of a side's pieces
+fn positional_score(side: usize) -> Score {
+    let mut score = Score::default();
+
+    for piece in side * 6..side * 6 + 6 {
+        let mut bitboard = unsafe { PIECE_BITBOARDS[piece] };
+
+        while bitboard != 0 {
+            let square = match index_lsb(bitboard) {
+                Ok(val) => val,
+                Err(e) => panic!("invalid bitboard: {:?}", e),
+            };
+
+            score += unsafe { PIECE_SQUARE_SCORES[piece][square] };
+
+            reset_bit!(bitboard, square);
+        }
+    }
+
+    // black scores are stored negated
+    if side == PieceColor::BLACK as usize {
+        score = -score;
+    }
+
+    score - material_score(side)
+}
+
+// print the evaluation broken down by term & side (scores from white's point of view)
+fn print_evaluation() {
+    let eval_info = init_eval_info();
+    let game_phase_score = unsafe { PHASE_SCORE };
+
+    let white = PieceColor::WHITE as usize;
+    let black = PieceColor::BLACK as usize;
+
+    let mut total = Score::default();
+
+    println!();
+    println!("      Term    |    White    |    Black    |    Total");
+    println!("              |   MG    EG  |   MG    EG  |   MG    EG");
+    println!(" -------------+-------------+-------------+------------");
+
+    let mut print_term = |name: &str, white_score: Score, black_score: Score| {
+        let term_score = white_score - black_score;
+
+        total += term_score;
+
+        println!(" {:>12} | {:>5} {:>5} | {:>5} {:>5} | {:>5} {:>5}",
+            name,
+            white_score.opening(), white_score.endgame(),
+            black_score.opening(), black_score.endgame(),
+            term_score.opening(), term_score.endgame(),
+        );
+    };
+
+    print_term("Material", material_score(white), material_score(black));
+    print_term("PST", positional_score(white), positional_score(black));
+
+    for (name, evaluate_term) in EVAL_TERMS.iter() {
+        print_term(name, evaluate_term(white, &eval_info), evaluate_term(black, &eval_info));
+    }
+
+    println!(" -------------+-------------+-------------+------------");
+    println!(" {:>12} |             |             | {:>5} {:>5}", "Total", total.opening(), total.endgame());
+    println!();
+    println!(" Game phase: {}", game_phase_score);
+    println!(" Endgame scale factor: {}/{}", endgame::scale_factor(), endgame::SCALE_NORMAL);
+    println!(" Tapered evaluation: {} (white side)", endgame::scale(total, endgame::scale_factor()).taper(game_phase_score));
+
+    if let Some(score) = endgame::evaluate() {
+        println!(" Known endgame, specialised evaluation: {} (white side)", score);
+    }
+
+    #[cfg(feature = "nnue")]
+    if nnue::is_active() {
+        println!(" NNUE evaluation is active, classical terms above are not used");
+    }
+
+    // same score from the side to move's point of view
+    println!(" Static evaluation: {} (side to move)", evaluate());
+    println!();
+}
+
+fn evaluate() -> i32 {
+    // specialised evaluation of known endgames
+    if let Some(score) = endgame::evaluate() {
+        unsafe {
+            return if SIDE == PieceColor::WHITE as i32 { score } else { -score };
+        }
+    }
+
+    // network evaluation when enabled, classical evaluation otherwise
+    #[cfg(feature = "nnue")]
+    if let Some(score) = nnue::evaluate() {
+        return score;
+    }
+
+    let (eval_score, game_phase_score) = unsafe { (EVAL_SCORE, PHASE_SCORE) };
+
+    // incremental scores must match the full recomputation
+    debug_assert_eq!(eval_score, compute_eval_score(), "incremental material & positional score out of sync");
+    debug_assert_eq!(game_phase_score, get_game_phase_score(), "incremental game phase score out of sync");
+
+    let eval_info = init_eval_info();
+
+    // material & positional scores are maintained by make_move
+    let mut score = eval_score;
+
+    for (_, evaluate_term) in EVAL_TERMS.iter() {
+        score += evaluate_term(PieceColor::WHITE as usize, &eval_info) - evaluate_term(PieceColor::BLACK as usize, &eval_info);
+    }
+
+    // shrink endgame score of drawish material
+    let score = endgame::scale(score, endgame::scale_factor());
+
+    // interpolate opening & endgame scores for the current game phase
+    let score = score.taper(game_phase_score);
+
+    unsafe {
+        if SIDE == PieceColor::WHITE as i32 {
+            score 
+        }else {
+            -score 
+        }
+    }
+
+}
+
+fn sort_moves(move_list: &mut Vec<u64>) {
+
+    let mut move_scores = move_list.clone().iter().map(|x| score_move(*x)).collect::<Vec<i32>>();
+
+    for current_move in 0..move_list.len() {
+        for next_move in current_move+1..move_list.len() {
+            if move_scores[current_move] < move_scores[next_move] {
+                move_list.swap(current_move, next_move);
+                move_scores.swap(current_move, next_move);
+            }
+        }
+    }
+}
+
+fn quiescence(mut alpha: i32, beta: i32, ht: &mut HashMap<u64, TTEntry>) -> i32 {
+
+    unsafe{
+    // every 2047 nodes
+    if (NODES & 2047) == 0{
+        communicate();  
+    }
+
+    // increment nodes count
+    NODES += 1;
+
+    stat!(quiescence_nodes);
+
+    // maximum ply reached
+    if PLY >= MAX_PLY - 1 {
+        return evaluate();
+    }
+
+    let pv_node = beta - alpha > 1;
+
+    // read hash entry
+    if let Some(val) = read_hash_entry(alpha, beta, 0, ht) {
+        if !pv_node {
+            stat!(tt_cutoffs);
+            return val;
+        }
+    }
+
+    // define hash flag
+    let mut hash_flag = HASH_FLAG_ALPHA;
+
+    let in_check = is_in_check();
+
+    // evaluate position
+    let evaluation = if in_check { -INFINITY } else { evaluate() };
+
+    // when in check there is no standing pat, all evasions are searched
+    if !in_check {
+        // fail-hard beta cutoff
+        if evaluation >= beta {
+            // node (move) fails high
+            return beta;
+        }
+
+        // a pawn on the 7th rank can also promote while capturing
+        let seventh_rank = if SIDE == PieceColor::WHITE as i32 { RANK_MASKS[48] } else { RANK_MASKS[8] };
+
+        let promotion_gain = if PIECE_BITBOARDS[SIDE as usize * 6 + Piece::P as usize] & seventh_rank != 0 {
+            MATERIAL_SCORE[Piece::Q as usize] - MATERIAL_SCORE[Piece::P as usize]
+        }else {
+            0
+        };
+
+        // delta pruning
+        // even winning a queen (and promoting) can't bring the score back to alpha
+        if evaluation + MATERIAL_SCORE[Piece::Q as usize] + promotion_gain + DELTA_MARGIN < alpha {
+            return alpha;
+        }
+
+        // found a better move
+        if evaluation > alpha {
+            // PV node (move)
+            alpha = evaluation;
+            hash_flag = HASH_FLAG_EXACT;
+        }
+    }
+
+    let mut legal_moves = generate_moves();
+
+    // checkmate
+    if in_check && legal_moves.is_empty() {
+        return -MATE_VALUE + PLY as i32;
+    }
+
+    legal_moves.sort_by_cached_key(|&x| std::cmp::Reverse(score_move(x)));
+
+    //sort_moves(&mut legal_moves);
+
+    // best move found so far (stored in the transposition table)
+    let mut best_move: u64 = 0;
+    
+    for mv in legal_moves.iter() {
+        // only captures are searched unless evading check
+        if !in_check && get_move_capture!(*mv) == 0 {
+            continue;
+        }
+
+        // delta pruning
+        // the captured material plus a safety margin doesn't reach alpha
+        if !in_check && get_move_promoted!(*mv) == 0
+        && evaluation + MATERIAL_SCORE[get_captured_piece(*mv) % 6] + DELTA_MARGIN < alpha {
+            continue;
+        }
+
+        // preserve board state
+        let board_copy = copy_board();
+
+        MOVE_STACK[PLY] = *mv;
+
+        // increment ply
+        PLY += 1;
+        
+        // increment repetition index & store hash key
+        REPETITION_INDEX += 1;
+        REPETITION_TABLE[REPETITION_INDEX] = HASH_KEY;
+
+        make_move(*mv, MOVE_TYPE::all_moves);
+
+        let score = -quiescence(-beta, -alpha, ht);
+
+        PLY-= 1;
+
+        // decrement repetition index
+        REPETITION_INDEX -= 1;
+
+        take_back(board_copy);
+
+        if STOPPED == 1 {
+            return 0;
+        }
+
+        // found a better move
+        if score > alpha {
+            alpha = score;
+            hash_flag = HASH_FLAG_EXACT;
+            best_move = *mv;
+            // fail-hard beta cutoff
+            if score >= beta {
+                // store hash entry with the score equal to beta
+                write_quiescence_hash_entry(beta, HASH_FLAG_BETA, *mv, ht);
+                // node (move) fails high
+                return beta;
+        }
+        }
+    }
+
+    // store hash entry with the score equal to alpha
+    write_quiescence_hash_entry(alpha, hash_flag, best_move, ht);
+
+    return alpha;
+    }
+    
+}
+
+// write quiescence search hash entry unless it would replace a deeper one
+fn write_quiescence_hash_entry(score: i32, hash_flag: u64, best_move: u64, ht: &mut HashMap<u64, TTEntry>) {
+    match probe_hash_entry(ht) {
+        Some(hash_entry) if hash_entry.depth > 0 => {},
+        _ => write_hash_entry(score, 0, hash_flag, best_move, ht),
+    }
+}
+
+// is the king of the side to move in check
+fn is_in_check() -> bool {
+    unsafe {
+        if SIDE == PieceColor::WHITE as i32 {
+            match index_lsb(PIECE_BITBOARDS[Piece::K as usize]) {
+                Ok(king_square) => is_square_attacked(king_square as u64, PieceColor::BLACK as u64),
+                Err(e) => panic!("error: {:?}", e),
+            }
+        }else {
+            match index_lsb(PIECE_BITBOARDS[Piece::k as usize]) {
+                Ok(king_square) => is_square_attacked(king_square as u64, PieceColor::WHITE as u64),
+                Err(e) => panic!("error: {:?}", e),
+            }
+        }
+    }
+}
+
+// late move reduction for a quiet move
+fn lmr_reduction(mv: u64, depth: usize, moves_searched: usize, pv_node: bool, gives_check: bool, history: i32) -> usize {
+    unsafe {
+        let mut reduction = LMR_TABLE[depth.min(MAX_PLY - 1)][moves_searched.min(MAX_MOVES - 1)] as i32;
+
+        // reduce PV nodes less
+        if pv_node {
+            reduction -= 1;
+        }
+
+        // reduce killer moves less
+        if KILLER_MOVES[0][PLY - 1] == mv as usize || KILLER_MOVES[1][PLY - 1] == mv as usize {
+            reduction -= 1;
+        }
+
+        // reduce moves with good history less and moves with bad history more
+        reduction -= (history / LMR_HISTORY_DIVISOR).clamp(-2, 2);
+
+        // reduce less if the move gives check
+        if gives_check {
+            reduction -= 1;
+        }
+
+        // never drop straight into quiescence search
+        reduction.clamp(0, depth as i32 - 2) as usize
+    }
+}
+
+// negamax alpha beta search
+fn negamax(mut alpha: i32, beta: i32, mut depth: usize, ht: &mut HashMap<u64, TTEntry>) -> i32 {
+    unsafe {
+
+        // init PV length
+        PV_LENGTH[PLY] = PLY as u64;
 
         // define score
         let mut score: i32 = 0;
@@ -3979,20 +5318,26 @@ fn negamax(mut alpha: i32, beta: i32, mut depth: usize, ht: &mut HashMap<u64, TT
         // define hash flag
         let mut hash_flag= HASH_FLAG_ALPHA;
         // if position repetition occurs return draw score
-        if PLY != 0 && is_repition() {
+        if PLY != 0 && (is_repition() || endgame::is_insufficient_material()) {
             return 0;
         }
         // a hack to find out the PV node
         let pv_node = beta - alpha > 1;
 
+        // move excluded by the singular extension search at this ply
+        let excluded_move = EXCLUDED_MOVES[PLY];
+
         // read hash entry
         // if the move has already been searched (hence has a value)
         // we just return the score for this move without searching it
-        if let Some(val) = read_hash_entry(alpha, beta, depth as u64, ht) {
-            score = val;
-            if PLY != 0 && !pv_node {
-                return score;
-            }   
+        if excluded_move == 0 {
+            if let Some(val) = read_hash_entry(alpha, beta, depth as u64, ht) {
+                score = val;
+                if PLY != 0 && !pv_node {
+                    stat!(tt_cutoffs);
+                    return score;
+                }   
+            }
         }
 
         // // every 2047 nodes
@@ -4001,7 +5346,8 @@ fn negamax(mut alpha: i32, beta: i32, mut depth: usize, ht: &mut HashMap<u64, TT
         }
 
         // Check if PLY reached the maximum ply allowed by PV_LENGTH and PV_TABLE
-        if PLY > 63 {
+        // (PV length of this ply is already set so the parent copies an empty line)
+        if PLY >= MAX_PLY - 1 {
             return evaluate();
         }
 
@@ -4009,39 +5355,66 @@ fn negamax(mut alpha: i32, beta: i32, mut depth: usize, ht: &mut HashMap<u64, TT
 
         if depth == 0 {
             // run quiescence search
-            return quiescence(alpha, beta);
+            return quiescence(alpha, beta, ht);
         }
         
         
 
         NODES += 1;
 
-        let mut in_check: bool = false;
+        let in_check = is_in_check();
 
-        if SIDE == PieceColor::WHITE as i32 {
-            let king_square = match index_lsb(PIECE_BITBOARDS[Piece::K as usize]){
-                Ok(val) => val as u64,
-                Err(e) => panic!("error: {:?}", e),
-            };
+        if in_check {
+            depth += 1;
+        }   
 
-            in_check = is_square_attacked(king_square, PieceColor::BLACK as u64);
-        }else {
-            let king_square = match index_lsb(PIECE_BITBOARDS[Piece::k as usize]){
-                Ok(val) => val as u64,
-                Err(e) => panic!("error: {:?}", e),
-            };
+        // static evaluation used by forward pruning
+        let static_eval = if in_check { -INFINITY } else { evaluate() };
+
+        // forward pruning is only safe in non-PV nodes away from mate scores
+        let can_prune = !pv_node && !in_check && PLY != 0 && excluded_move == 0 && beta.abs() < MATE_SCORE;
 
-            in_check = is_square_attacked(king_square, PieceColor::WHITE as u64);
+        // reverse futility pruning
+        // static evaluation beats beta by a safe margin
+        if can_prune && depth <= RFP_DEPTH && static_eval - RFP_MARGIN * depth as i32 >= beta {
+            return beta;
         }
 
-        if in_check {
-            depth += 1;
-        }   
+        // razoring
+        // static evaluation is too far below alpha, only tactics can save the node
+        if can_prune && depth <= RAZOR_DEPTH && static_eval + RAZOR_MARGIN * depth as i32 <= alpha {
+            score = quiescence(alpha, alpha + 1, ht);
+
+            if STOPPED == 1 {
+                SEARCH_COMPLETE = false;
+                return 0;
+            }
+
+            if score <= alpha {
+                return alpha;
+            }
+        }
+
+        // non-pawn material of the side to move
+        let non_pawn_pieces = if SIDE == PieceColor::WHITE as i32 {
+            count_bits(OCCUPANCIES[PieceColor::WHITE as usize] ^ PIECE_BITBOARDS[Piece::P as usize] ^ PIECE_BITBOARDS[Piece::K as usize])
+        }else {
+            count_bits(OCCUPANCIES[PieceColor::BLACK as usize] ^ PIECE_BITBOARDS[Piece::p as usize] ^ PIECE_BITBOARDS[Piece::k as usize])
+        };
 
         // null move pruning
-        if depth >= 3 && !in_check && PLY != 0 {
+        // (never in king & pawns endings where zugzwang is the rule)
+        if can_prune && depth >= 3 && static_eval >= beta && non_pawn_pieces > 0 && !NULL_MOVE_VERIFICATION[PLY] {
+            // adaptive reduction: deeper searches and larger margins over beta reduce more
+            let reduction = (NULL_MOVE_REDUCTION + depth / 6 + ((static_eval - beta) / 200).min(2) as usize).min(depth - 1);
+
+            stat!(null_move_searches);
+
             // preserve board state
-            let (piece_bitboards_copy, occupancies_copy, side_copy, enpassant_copy, castle_copy, hash_key_copy) = copy_board();
+            let board_copy = copy_board();
+            // no move leads to the child node
+            MOVE_STACK[PLY] = 0;
+
             // increment ply
             PLY += 1;
 
@@ -4061,7 +5434,7 @@ fn negamax(mut alpha: i32, beta: i32, mut depth: usize, ht: &mut HashMap<u64, TT
             // hash side
             HASH_KEY ^= SIDE_KEY;   
 
-            score = -negamax(-beta, -beta+1, depth-1-2, ht);
+            score = -negamax(-beta, -beta+1, depth - 1 - reduction, ht);
 
             // decrement ply
             PLY -= 1;
@@ -4069,7 +5442,7 @@ fn negamax(mut alpha: i32, beta: i32, mut depth: usize, ht: &mut HashMap<u64, TT
             REPETITION_INDEX -= 1;
 
             // take back move
-            take_back(piece_bitboards_copy, occupancies_copy, side_copy, enpassant_copy, castle_copy, hash_key_copy);
+            take_back(board_copy);
 
             if STOPPED == 1 {
                 SEARCH_COMPLETE = false;
@@ -4077,11 +5450,29 @@ fn negamax(mut alpha: i32, beta: i32, mut depth: usize, ht: &mut HashMap<u64, TT
             }
 
             if score >= beta {
-                return beta;
+                // in zugzwang-prone endgames (few pieces left) verify the null move
+                // cutoff with a reduced depth search of our own moves
+                if non_pawn_pieces <= NULL_MOVE_VERIFICATION_PIECES && depth > reduction + 1 {
+                    NULL_MOVE_VERIFICATION[PLY] = true;
+                    score = negamax(beta - 1, beta, depth - 1 - reduction, ht);
+                    NULL_MOVE_VERIFICATION[PLY] = false;
+
+                    // discard PV collected by the verification search
+                    PV_LENGTH[PLY] = PLY as u64;
+
+                    if STOPPED == 1 {
+                        SEARCH_COMPLETE = false;
+                        return 0;
+                    }
+                }
+
+                if score >= beta {
+                    stat!(null_move_cutoffs);
+                    return beta;
+                }
             }
         }
 
-
         let mut legal_moves = generate_moves();
 
         // if we are following principle variation line
@@ -4090,14 +5481,77 @@ fn negamax(mut alpha: i32, beta: i32, mut depth: usize, ht: &mut HashMap<u64, TT
             enable_pv_scoring(&legal_moves);
         }
 
-        legal_moves.sort_by_key(|&x|  std::cmp::Reverse(score_move(x)));
+        legal_moves.sort_by_cached_key(|&x| std::cmp::Reverse(score_move(x)));
+
+        // singular extension
+        // if the hash move is the only one holding the score stored in the
+        // transposition table, search it one ply deeper
+        let mut singular_move: u64 = 0;
+
+        if depth >= SINGULAR_DEPTH && PLY != 0 && excluded_move == 0 {
+            if let Some(hash_entry) = probe_hash_entry(ht) {
+                if hash_entry.best_move != 0
+                && hash_entry.flag != HASH_FLAG_ALPHA
+                && hash_entry.depth + SINGULAR_TT_DEPTH_MARGIN >= depth as u64
+                && hash_entry.score.abs() < MATE_SCORE
+                && legal_moves.contains(&hash_entry.best_move)
+                {
+                    let singular_beta = hash_entry.score - SINGULAR_MARGIN * depth as i32;
+
+                    // search every other move at reduced depth
+                    EXCLUDED_MOVES[PLY] = hash_entry.best_move;
+                    score = negamax(singular_beta - 1, singular_beta, (depth - 1) / 2, ht);
+                    EXCLUDED_MOVES[PLY] = 0;
+
+                    // discard PV collected by the verification search
+                    PV_LENGTH[PLY] = PLY as u64;
+
+                    if STOPPED == 1 {
+                        SEARCH_COMPLETE = false;
+                        return 0;
+                    }
+
+                    if score < singular_beta {
+                        singular_move = hash_entry.best_move;
+                    }
+                }
+            }
+        }
 
         let mut moves_searched = 0;
 
+        // quiet moves searched so far (late move pruning)
+        let mut quiets_searched = 0;
+
+        // futility pruning
+        // quiet moves can't raise the static evaluation above alpha
+        let futility_pruning = can_prune && depth <= FUTILITY_DEPTH && static_eval + FUTILITY_MARGIN[depth] <= alpha;
+
+        // best move found so far (stored in the transposition table)
+        let mut best_move: u64 = 0;
+
+        // moves that failed to produce a cutoff (history maluses)
+        let mut quiets_tried: Vec<u64> = Vec::new();
+        let mut captures_tried: Vec<(u64, usize)> = Vec::new();
+
         //sort_moves(&mut legal_moves);
 
         for mv in legal_moves.iter() {
-            let (piece_bitboards_copy, occupancies_copy, side_copy, enpassant_copy, castle_copy, hash_key_copy) = copy_board();
+            // skip the move being verified by singular extension search
+            if *mv == excluded_move {
+                continue;
+            }
+
+            let board_copy = copy_board();
+
+            // history of quiet moves & captured piece of captures (before the board changes)
+            let (history, captured_piece) = if get_move_capture!(*mv) == 0 {
+                (quiet_history(*mv), 0)
+            }else {
+                (0, get_captured_piece(*mv))
+            };
+
+            MOVE_STACK[PLY] = *mv;
 
             PLY += 1;
 
@@ -4105,12 +5559,37 @@ fn negamax(mut alpha: i32, beta: i32, mut depth: usize, ht: &mut HashMap<u64, TT
             REPETITION_INDEX += 1;
             REPETITION_TABLE[REPETITION_INDEX] = HASH_KEY;
 
-            make_move(*mv, MOVE_TYPE::all_moves);
+            make_move(*mv, MOVE_TYPE::all_moves);
+
+            let quiet_move = get_move_capture!(*mv) == 0 && get_move_promoted!(*mv) == 0;
+
+            // does the move give check
+            let gives_check = is_in_check();
+
+            // prune quiet moves that neither give check nor stand a chance to raise alpha
+            if moves_searched > 0 && quiet_move && !gives_check && (
+                // futility pruning
+                futility_pruning
+                // late move pruning
+                || (can_prune && depth <= LMP_DEPTH && quiets_searched >= LMP_MOVE_COUNT[depth])
+            ) {
+                PLY -= 1;
+                REPETITION_INDEX -= 1;
+                take_back(board_copy);
+                continue;
+            }
+
+            if quiet_move {
+                quiets_searched += 1;
+            }
+
+            // extend singular move
+            let extension = if *mv == singular_move { 1 } else { 0 };
 
             // full depth search
             if moves_searched == 0 {
 
-                score = -negamax(-beta, -alpha, depth - 1, ht);
+                score = -negamax(-beta, -alpha, depth - 1 + extension, ht);
                     
             }else{
                 // late move reduction (LMR)
@@ -4121,7 +5600,16 @@ fn negamax(mut alpha: i32, beta: i32, mut depth: usize, ht: &mut HashMap<u64, TT
                 && get_move_capture!(*mv) == 0 
                 && get_move_promoted!(*mv) == 0 
                 {
-                    score = -negamax(-alpha - 1, -alpha, depth - 2, ht);
+                    let reduction = lmr_reduction(*mv, depth, moves_searched, pv_node, gives_check, history);
+
+                    score = -negamax(-alpha - 1, -alpha, depth - 1 - reduction, ht);
+
+                    stat!(lmr_searches);
+
+                    // reduced search raised alpha, search again at full depth
+                    if score > alpha {
+                        stat!(lmr_researches);
+                    }
 
                 }else{
                     // hack to ensure that full-depth search is done
@@ -4134,7 +5622,7 @@ fn negamax(mut alpha: i32, beta: i32, mut depth: usize, ht: &mut HashMap<u64, TT
                     // the rest of the moves are searched with the goal of proving that they are all bad.
                     // It's possible to do this a bit faster than a search that worries that one
                     // of the remaining moves might be good. */
-                    score = -negamax(-alpha - 1, -alpha, depth-1, ht);
+                    score = -negamax(-alpha - 1, -alpha, depth - 1 + extension, ht);
 
                     // /* If the algorithm finds out that it was wrong, and that one of the
                     // subsequent moves was better than the first PV move, it has to search again,
@@ -4143,7 +5631,7 @@ fn negamax(mut alpha: i32, beta: i32, mut depth: usize, ht: &mut HashMap<u64, TT
                     // "bad move proof" search referred to earlier. */
                     if score > alpha && score < beta {
                         
-                        score = -negamax(-beta, -alpha, depth-1, ht);
+                        score = -negamax(-beta, -alpha, depth - 1 + extension, ht);
                     }
                 }
                     
@@ -4153,7 +5641,7 @@ fn negamax(mut alpha: i32, beta: i32, mut depth: usize, ht: &mut HashMap<u64, TT
 
             REPETITION_INDEX -= 1;
 
-            take_back(piece_bitboards_copy, occupancies_copy, side_copy, enpassant_copy, castle_copy, hash_key_copy);
+            take_back(board_copy);
 
             if STOPPED == 1 {
                 SEARCH_COMPLETE = false;
@@ -4168,16 +5656,12 @@ fn negamax(mut alpha: i32, beta: i32, mut depth: usize, ht: &mut HashMap<u64, TT
                 // switch hash flag from storing score for fail-low node
                 // to the one storing score for PV node
                 hash_flag = HASH_FLAG_EXACT;
-
-                // on quiet moves
-                if get_move_capture!(*mv) == 0 {
-                    // store history moves
-                    HISTORY_MOVES[get_move_piece!(*mv) as usize][get_move_target!(*mv) as usize] += depth;
-                }
                     
                 // PV node (move)
                 alpha = score;
 
+                best_move = *mv;
+
                 // enable found pv flag
                 // found_pv = true;
 
@@ -4185,7 +5669,7 @@ fn negamax(mut alpha: i32, beta: i32, mut depth: usize, ht: &mut HashMap<u64, TT
                 PV_TABLE[PLY][PLY] = *mv;
 
                 // loop over the next ply
-                if PLY < 63 {
+                if PLY + 1 < MAX_PLY {
                     for next_ply in PLY +1 .. PV_LENGTH[PLY +1] as usize {
                         // copy move from deeper ply into a current ply's line
                         PV_TABLE[PLY][next_ply] = PV_TABLE[PLY+1][next_ply];
@@ -4198,20 +5682,54 @@ fn negamax(mut alpha: i32, beta: i32, mut depth: usize, ht: &mut HashMap<u64, TT
 
                 // fail-hard beta cutoff
                 if score >= beta {
+                    stat!(beta_cutoffs);
+
+                    if moves_searched == 1 {
+                        stat!(first_move_cutoffs);
+                    }
+
                     // store hash entry with the score equal to beta
-                    write_hash_entry(beta, depth as u64, HASH_FLAG_BETA, ht);
+                    if excluded_move == 0 {
+                        write_hash_entry(beta, depth as u64, HASH_FLAG_BETA, *mv, ht);
+                    }
+                    let bonus = ((depth * depth) as i32).min(HISTORY_BONUS_MAX);
+
                     // on quiet moves
                     if get_move_capture!(*mv) == 0 {
                         // store killer moves
                         KILLER_MOVES[1][PLY] = KILLER_MOVES[0][PLY];
                         KILLER_MOVES[0][PLY] = *mv as usize;
-                    } 
+
+                        // store counter move
+                        if PLY > 0 && MOVE_STACK[PLY - 1] != 0 {
+                            COUNTER_MOVES[get_move_piece!(MOVE_STACK[PLY - 1]) as usize][get_move_target!(MOVE_STACK[PLY - 1]) as usize] = *mv;
+                        }
+
+                        // reward the cutoff move & punish quiet moves searched before it
+                        update_quiet_histories(*mv, bonus);
+                        for quiet in quiets_tried.iter() {
+                            update_quiet_histories(*quiet, -bonus);
+                        }
+                    }else {
+                        update_capture_history(*mv, captured_piece, bonus);
+                    }
+
+                    // captures searched before the cutoff move failed as well
+                    for (capture, captured) in captures_tried.iter() {
+                        update_capture_history(*capture, *captured, -bonus);
+                    }
 
                     return beta;
                 }
 
             }
 
+            if get_move_capture!(*mv) == 0 {
+                quiets_tried.push(*mv);
+            }else {
+                captures_tried.push((*mv, captured_piece));
+            }
+
                 
         }
 
@@ -4224,7 +5742,9 @@ fn negamax(mut alpha: i32, beta: i32, mut depth: usize, ht: &mut HashMap<u64, TT
             }
         }
         // store hash entry with the score equal to alpha
-        write_hash_entry(alpha, depth as u64, hash_flag, ht);
+        if excluded_move == 0 {
+            write_hash_entry(alpha, depth as u64, hash_flag, best_move, ht);
+        }
         return alpha;
     }
 }
@@ -4241,6 +5761,7 @@ fn reset_time_control() {
         TIMESET= 0;
         STOPPED= 0;
         SEARCH_COMPLETE = true;
+        NODE_LIMIT = 0;
     }
 }
 
@@ -4249,6 +5770,18 @@ fn parse_go(command: String, ht: &mut HashMap<u64, TTEntry>) {
 
     reset_time_control();
 
+    // play from the opening book before searching
+    if let Some(mv) = polyglot::probe_book() {
+        unsafe {
+            BEST_MOVE = mv;
+        }
+
+        println!("info string book move {}", get_uci_move(mv));
+        println!("bestmove {}", get_uci_move(mv));
+
+        return;
+    }
+
     let mut depth = -1;
 
     if command.chars().skip(3).take(5).collect::<Vec<char>>().iter().collect::<String>() == "depth" {
@@ -4262,6 +5795,18 @@ fn parse_go(command: String, ht: &mut HashMap<u64, TTEntry>) {
         }
     // infinite search
     }
+    if command.chars().skip(3).take(5).collect::<Vec<char>>().iter().collect::<String>() == "nodes" {
+        let nodes = command.chars().skip(9).collect::<Vec<char>>().iter().collect::<String>();
+
+        let nodes = match nodes.trim().parse::<usize>() {
+            Ok(val) => val,
+            Err(e) => panic!("unknown value for nodes: {}", e),
+        };
+
+        unsafe {
+            NODE_LIMIT = nodes;
+        }
+    }
     if command.chars().skip(3).take(8).collect::<Vec<char>>().iter().collect::<String>() == "infinite"{
 
     }if command.chars().skip(3).take(5).collect::<Vec<char>>().iter().collect::<String>() == "wtime" {
@@ -4293,113 +5838,519 @@ fn parse_go(command: String, ht: &mut HashMap<u64, TTEntry>) {
                 if SIDE == PieceColor::BLACK as i32 {
                     TIME = btime as i64;
                 }
-                
+                
+            }
+        }
+        
+        if time_controls.len() >= 6 {
+            let winc = match time_controls[5].parse::<usize>() {
+                Ok(val) => val,
+                Err(e) => panic!("unknown value for winc: {}", e),
+            };
+    
+            unsafe {
+                if SIDE == PieceColor::WHITE as i32 {
+                    INC = winc as i64;
+                }
+                
+            }
+        }
+        
+        if time_controls.len() >= 8 {
+            let binc = match time_controls[7].parse::<usize>() {
+                Ok(val) => val,
+                Err(e) => panic!("unknown value for binc: {}", e),
+            };
+    
+            unsafe {
+                if SIDE == PieceColor::BLACK as i32 {
+                    INC = binc as i64;
+                }
+                
+            }
+        }
+        
+
+
+    }
+
+    if command.chars().skip(3).take(9).collect::<Vec<char>>().iter().collect::<String>() == "movestogo" {
+        let movestogo = command.chars().skip(13).collect::<Vec<char>>().iter().collect::<String>();
+
+        let movestogo = match movestogo.parse::<usize>() {
+            Ok(val) => val,
+            Err(e) => panic!("unknown value for movestogo: {}", e),
+        };
+
+        unsafe {
+            MOVESTOGO = movestogo as u64;
+        }
+    }
+
+    if command.chars().skip(3).take(8).collect::<Vec<char>>().iter().collect::<String>() == "movetime" {
+        let movetime= command.chars().skip(12).collect::<Vec<char>>().iter().collect::<String>();
+
+        let movetime = match movetime.parse::<usize>() {
+            Ok(val) => val,
+            Err(e) => panic!("unknown value for movetime: {}", e),
+        };
+
+        unsafe {
+            MOVETIME = movetime as i64;
+        }
+
+    }
+
+    unsafe {
+        if MOVETIME !=-1 {
+            TIME= MOVETIME;
+            MOVESTOGO= 1;
+        }
+
+        STARTTIME = get_time_ms() as u64;
+
+        if TIME!= -1 {
+            TIMESET = 1;
+
+            TIME /= MOVESTOGO as i64;
+            TIME  -= 50;
+            // if time is up
+            if TIME < 0 {
+                // restore negative time to 0
+                TIME = 0;
+                INC -= 50;
+                // timing for 0 seconds left and no inc
+                if INC < 0 {
+                    INC = 1;
+                }
+            }
+            
+            
+            STOPTIME = STARTTIME + TIME as u64 + INC as u64;
+        }
+    }
+
+    if depth == -1 || depth >= MAX_PLY as i32 {
+        depth = MAX_PLY as i32 - 1;
+    }
+    
+    search_position(depth as usize, ht);
+
+}
+
+// position & game result (from white's point of view) used for tuning
+struct TuningPosition {
+    board: BoardCopy,
+    result: f64,
+}
+
+/*
+    Parse a tuning dataset line: a FEN followed by the game result, either
+    "<fen> | <score> | <result>" (datagen output) or "<fen> <result>" where
+    the result may be written as 1-0, 0-1, 1/2-1/2, 1.0, 0.5 or 0.0 and be
+    wrapped in brackets or quotes (e.g. [0.5] or c9 "1-0";).
+*/
+fn parse_tuning_line(line: &str) -> Option<(String, f64)> {
+    let line = line.trim();
+
+    let (fen, result) = if line.contains('|') {
+        let fields = line.split('|').collect::<Vec<&str>>();
+
+        (fields[0].trim().to_string(), fields[fields.len() - 1].trim())
+    }else {
+        let (fen, result) = line.rsplit_once(char::is_whitespace)?;
+        let fen = fen.trim().trim_end_matches("c9").trim();
+
+        (fen.to_string(), result)
+    };
+
+    let result = match result.trim_matches(|c: char| c == '[' || c == ']' || c == '"' || c == ';') {
+        "1-0" | "1.0" | "1" => 1.0,
+        "0-1" | "0.0" | "0" => 0.0,
+        "1/2-1/2" | "0.5" => 0.5,
+        _ => return None,
+    };
+
+    if fen.split_whitespace().count() < 4 {
+        return None;
+    }
+
+    Some((fen, result))
+}
+
+// mean squared error between game results & the sigmoid of the evaluation
+fn tuning_error(positions: &[TuningPosition], k: f64) -> f64 {
+    let mut error = 0.0;
+
+    for position in positions {
+        take_back(position.board);
+
+        let mut score = unsafe {
+            // stored score was computed with other weights
+            EVAL_SCORE = compute_eval_score();
+
+            evaluate()
+        };
+
+        // white's point of view
+        if unsafe { SIDE } == PieceColor::BLACK as i32 {
+            score = -score;
+        }
+
+        let sigmoid = 1.0 / (1.0 + 10f64.powf(-k * score as f64 / 400.0));
+
+        error += (position.result - sigmoid).powi(2);
+    }
+
+    error / positions.len().max(1) as f64
+}
+
+// local search passes over the weights when none are given
+const TUNE_ITERATIONS: usize = 100;
+
+// Texel tuning of the evaluation parameters in use by local search
+fn tune(dataset_path: &str, output_path: &str, iterations: usize, char_pieces: &HashMap<char, u32>) {
+    let dataset = match std::fs::read_to_string(dataset_path) {
+        Ok(val) => val,
+        Err(e) => {
+            println!("info string cannot read {}: {}", dataset_path, e);
+            return;
+        }
+    };
+
+    let mut positions = Vec::new();
+
+    // lines without a result or with an invalid fen
+    let mut skipped = 0;
+
+    for line in dataset.lines().filter(|line| !line.trim().is_empty()) {
+        match parse_tuning_line(line) {
+            Some((fen, result)) if try_parse_fen(&fen, char_pieces).is_ok() => {
+                positions.push(TuningPosition { board: copy_board(), result });
+            },
+            _ => skipped += 1,
+        }
+    }
+
+    println!("info string tuning on {} positions, {} lines skipped", positions.len(), skipped);
+
+    if positions.is_empty() {
+        return;
+    }
+
+    let initial_params = unsafe { EVAL_PARAMS };
+
+    // the classical evaluation is tuned even when a network is loaded
+    #[cfg(feature = "nnue")]
+    let use_nnue = unsafe { nnue::USE_NNUE };
+
+    #[cfg(feature = "nnue")]
+    unsafe {
+        nnue::USE_NNUE = false;
+    }
+
+    // scaling constant of the sigmoid fitting the current evaluation best
+    let mut k = 1.0;
+    let mut best_error = tuning_error(&positions, k);
+
+    for step in [0.1, 0.01, 0.001] {
+        for direction in [1.0, -1.0] {
+            loop {
+                let error = tuning_error(&positions, k + step * direction);
+
+                if error >= best_error {
+                    break;
+                }
+
+                k += step * direction;
+                best_error = error;
+            }
+        }
+    }
+
+    println!("info string K {:.3} error {:.8}", k, best_error);
+
+    let mut weights = initial_params.to_vec();
+
+    for iteration in 1..=iterations {
+        let mut improved = 0;
+
+        for index in 0..weights.len() {
+            for delta in [1, -1] {
+                weights[index] += delta;
+
+                set_eval_params(EvalParams::from_vec(&weights));
+
+                let error = tuning_error(&positions, k);
+
+                if error < best_error {
+                    best_error = error;
+                    improved += 1;
+                    break;
+                }
+
+                weights[index] -= delta;
+            }
+        }
+
+        // save progress after every iteration
+        let params = EvalParams::from_vec(&weights);
+
+        match std::fs::write(output_path, params.to_text()) {
+            Ok(_) => println!("info string iteration {} error {:.8} improved {} saved to {}", iteration, best_error, improved, output_path),
+            Err(e) => println!("info string cannot write {}: {}", output_path, e),
+        }
+
+        if improved == 0 {
+            break;
+        }
+    }
+
+    // keep playing with the parameters in use before tuning
+    set_eval_params(initial_params);
+
+    #[cfg(feature = "nnue")]
+    unsafe {
+        nnue::USE_NNUE = use_nnue;
+    }
+}
+
+// play a move in a game (not a search) keeping repetition history
+fn make_game_move(mv: u64) {
+    unsafe {
+        REPETITION_INDEX += 1;
+        REPETITION_TABLE[REPETITION_INDEX] = HASH_KEY;
+    }
+
+    make_move(mv, MOVE_TYPE::all_moves);
+}
+
+// has the current position occured three times in the game
+fn is_threefold_repetition() -> bool {
+    unsafe {
+        (0..=REPETITION_INDEX).filter(|&index| REPETITION_TABLE[index] == HASH_KEY).count() >= 2
+    }
+}
+
+// longest self-play game before it is adjudicated as a draw
+static DATAGEN_MAX_PLIES: usize = 400;
+
+/*
+    Self-play training data generator
+
+    datagen <file> [games N] [depth D] [nodes N] [randomplies R] [seed S]
+
+    Every game starts with R random legal moves from the start position
+    (seeded by S through get_random_u32_number, so runs are reproducible),
+    then both sides play the best move of a depth D (and optionally N nodes)
+    search. Each searched position is written as one line:
+
+        <fen> | <score> | <result>
+
+    score:  search score in centipawns from white's point of view
+    result: game result from white's point of view (1.0, 0.5 or 0.0)
+
+    Positions in check, with a capture or promotion as best move and with
+    mate scores are skipped. Games end on mate, stalemate, threefold
+    repetition, insufficient material, a mate score (adjudicated) or DATAGEN_MAX_PLIES.
+*/
+#[derive(Copy, Clone)]
+struct DatagenConfig {
+    games: usize,
+    depth: usize,
+    // node limit of a search (0 for none)
+    nodes: usize,
+    random_plies: usize,
+    seed: u32,
+}
+
+fn datagen(path: &str, config: DatagenConfig, char_pieces: &HashMap<char, u32>, ht: &mut HashMap<u64, TTEntry>) {
+    let mut file = match std::fs::File::create(path) {
+        Ok(val) => val,
+        Err(e) => {
+            println!("info string cannot create {}: {}", path, e);
+            return;
+        }
+    };
+
+    unsafe {
+        // xorshift state must not be zero
+        RANDOM_SEED = config.seed.max(1);
+        SEARCH_OUTPUT = false;
+    }
+
+    let mut total_positions = 0;
+
+    for game in 1..=config.games {
+        ht.clear();
+        clear_history();
+        parse_fen(START_POSTITION, char_pieces);
+
+        // random opening
+        for _ in 0..config.random_plies {
+            let move_list = generate_moves();
+
+            if move_list.is_empty() {
+                break;
+            }
+
+            make_game_move(move_list[get_random_u32_number() as usize % move_list.len()]);
+        }
+
+        let mut positions: Vec<(String, i32)> = Vec::new();
+        let mut result = 0.5;
+
+        for _ in 0..DATAGEN_MAX_PLIES {
+            let side = unsafe { SIDE };
+
+            // checkmate or stalemate
+            if generate_moves().is_empty() {
+                if is_in_check() {
+                    result = if side == PieceColor::WHITE as i32 { 0.0 } else { 1.0 };
+                }
+
+                break;
             }
-        }
-        
-        if time_controls.len() >= 6 {
-            let winc = match time_controls[5].parse::<usize>() {
-                Ok(val) => val,
-                Err(e) => panic!("unknown value for winc: {}", e),
-            };
-    
+
+            // draw by repetition or insufficient material
+            if is_threefold_repetition() || endgame::is_insufficient_material() {
+                break;
+            }
+
+            reset_time_control();
+
             unsafe {
-                if SIDE == PieceColor::WHITE as i32 {
-                    INC = winc as i64;
-                }
-                
+                NODE_LIMIT = config.nodes;
+            }
+
+            search_position(config.depth, ht);
+
+            let (best_move, score) = unsafe { (BEST_MOVE, BEST_SCORE) };
+
+            if best_move == 0 {
+                break;
+            }
+
+            let white_score = if side == PieceColor::WHITE as i32 { score } else { -score };
+
+            // adjudicate found mates
+            if score.abs() > MATE_SCORE {
+                result = if white_score > 0 { 1.0 } else { 0.0 };
+
+                break;
+            }
+
+            // keep quiet positions only
+            if !is_in_check() && get_move_capture!(best_move) == 0 && get_move_promoted!(best_move) == 0 {
+                positions.push((get_fen(), white_score));
             }
+
+            make_game_move(best_move);
         }
-        
-        if time_controls.len() >= 8 {
-            let binc = match time_controls[7].parse::<usize>() {
-                Ok(val) => val,
-                Err(e) => panic!("unknown value for binc: {}", e),
-            };
-    
-            unsafe {
-                if SIDE == PieceColor::BLACK as i32 {
-                    INC = binc as i64;
-                }
-                
+
+        for (fen, score) in positions.iter() {
+            if let Err(e) = writeln!(file, "{} | {} | {:.1}", fen, score, result) {
+                println!("info string cannot write {}: {}", path, e);
+                break;
             }
         }
-        
 
+        total_positions += positions.len();
 
+        println!("info string game {} result {:.1} positions {} total {}", game, result, positions.len(), total_positions);
     }
 
-    if command.chars().skip(3).take(9).collect::<Vec<char>>().iter().collect::<String>() == "movestogo" {
-        let movestogo = command.chars().skip(13).collect::<Vec<char>>().iter().collect::<String>();
+    unsafe {
+        SEARCH_OUTPUT = true;
+    }
+}
 
-        let movestogo = match movestogo.parse::<usize>() {
-            Ok(val) => val,
-            Err(e) => panic!("unknown value for movestogo: {}", e),
-        };
+// handle "setoption name <name> value <value>"
+fn parse_setoption(command: String, ht: &mut HashMap<u64, TTEntry>) {
+    let name = match command.find("name ") {
+        Some(index) => command[index + 5..].split(" value").next().unwrap_or_default().trim().to_string(),
+        None => return,
+    };
+
+    let value = match command.find(" value ") {
+        Some(index) => command[index + 7..].trim().to_string(),
+        None => String::new(),
+    };
 
+    #[cfg(feature = "nnue")]
+    if name.eq_ignore_ascii_case("UseNNUE") {
         unsafe {
-            MOVESTOGO = movestogo as u64;
+            nnue::USE_NNUE = value.eq_ignore_ascii_case("true");
         }
-    }
 
-    if command.chars().skip(3).take(8).collect::<Vec<char>>().iter().collect::<String>() == "movetime" {
-        let movetime= command.chars().skip(12).collect::<Vec<char>>().iter().collect::<String>();
+        // cached scores were computed by the other evaluation
+        ht.clear();
 
-        let movetime = match movetime.parse::<usize>() {
-            Ok(val) => val,
-            Err(e) => panic!("unknown value for movetime: {}", e),
-        };
+        return;
+    }
 
+    if name.eq_ignore_ascii_case("OwnBook") {
         unsafe {
-            MOVETIME = movetime as i64;
+            polyglot::OWN_BOOK = value.eq_ignore_ascii_case("true");
         }
 
+        return;
     }
 
-    unsafe {
-        if MOVETIME !=-1 {
-            TIME= MOVETIME;
-            MOVESTOGO= 1;
+    if name.eq_ignore_ascii_case("BookBestMove") {
+        unsafe {
+            polyglot::BOOK_BEST_MOVE = value.eq_ignore_ascii_case("true");
         }
 
-        STARTTIME = get_time_ms() as u64;
-
-        if TIME!= -1 {
-            TIMESET = 1;
+        return;
+    }
 
-            TIME /= MOVESTOGO as i64;
-            TIME  -= 50;
-            // if time is up
-            if TIME < 0 {
-                // restore negative time to 0
-                TIME = 0;
-                INC -= 50;
-                // timing for 0 seconds left and no inc
-                if INC < 0 {
-                    INC = 1;
-                }
+    if name.eq_ignore_ascii_case("BookFile") {
+        if value.is_empty() || value == "<empty>" {
+            polyglot::unload_book();
+        }else {
+            match polyglot::load_book(&value) {
+                Ok(entries) => println!("info string loaded {} book entries from {}", entries, value),
+                Err(e) => println!("info string failed to load book: {}", e),
             }
-            
-            
-            STOPTIME = STARTTIME + TIME as u64 + INC as u64;
         }
-    }
 
-    if depth == -1 {
-        depth = 64;
-    }
-    unsafe {
-        println!("time:{} start:{} stop:{} depth:{} timeset:{}", TIME, STARTTIME, STOPTIME, depth, TIMESET);
+        return;
     }
-    
-    search_position(depth as usize, ht);
 
+    if name.eq_ignore_ascii_case("EvalFile") {
+        if value.is_empty() || value == "<empty>" {
+            set_eval_params(DEFAULT_EVAL_PARAMS);
+            #[cfg(feature = "nnue")]
+            nnue::unload_network();
+            println!("info string using default evaluation parameters");
+        }else {
+            match load_eval_file(&value) {
+                Ok(_) => println!("info string evaluation loaded from {}", value),
+                Err(e) => println!("info string failed to load evaluation: {}", e),
+            }
+        }
+
+        // cached scores were computed with other weights
+        ht.clear();
+    }
 }
 
-fn uci_loop(char_pieces: &HashMap<char, u32>, ht: &mut HashMap<u64, TTEntry>) {
+// engine identification & options
+fn print_uci_id() {
     println!("id name cheng");
     println!("id author Ramez Essam");
+    println!("option name EvalFile type string default <empty>");
+    println!("option name OwnBook type check default false");
+    println!("option name BookFile type string default <empty>");
+    println!("option name BookBestMove type check default false");
+    #[cfg(feature = "nnue")]
+    println!("option name UseNNUE type check default true");
     println!("uciok");
+}
+
+fn uci_loop(char_pieces: &HashMap<char, u32>, ht: &mut HashMap<u64, TTEntry>) {
+    print_uci_id();
     let mut input = String::new();
     
     loop {
@@ -4416,18 +6367,118 @@ fn uci_loop(char_pieces: &HashMap<char, u32>, ht: &mut HashMap<u64, TTEntry>) {
         }else if input.chars().take(10).collect::<Vec<char>>().iter().collect::<String>() == "ucinewgame" {
             parse_position("position startpos".to_string(), char_pieces);
             ht.clear();
+            clear_history();
         }else if input.chars().take(2).collect::<Vec<char>>().iter().collect::<String>() == "go" {
             parse_go(input.clone(), ht);
         }else if input.chars().take(4).collect::<Vec<char>>().iter().collect::<String>() == "quit" {
             break;
         }else if input.chars().take(3).collect::<Vec<char>>().iter().collect::<String>() == "uci" {
-            println!("id name cheng");
-            println!("id author Ramez Essam");
-            println!("uciok");
+            print_uci_id();
+        }else if input.chars().take(9).collect::<Vec<char>>().iter().collect::<String>() == "setoption" {
+            parse_setoption(input.clone(), ht);
+        }else if input.chars().take(10).collect::<Vec<char>>().iter().collect::<String>() == "saveparams" {
+            let path = input.chars().skip(11).collect::<Vec<char>>().iter().collect::<String>();
+
+            match save_eval_params(path.trim()) {
+                Ok(_) => println!("info string evaluation parameters saved to {}", path.trim()),
+                Err(e) => println!("info string failed to save evaluation parameters: {}", e),
+            }
+        }else if input.chars().take(7).collect::<Vec<char>>().iter().collect::<String>() == "datagen" {
+            // datagen <file> [games N] [depth D] [nodes N] [randomplies R] [seed S]
+            let args = input.split_whitespace().skip(1).collect::<Vec<&str>>();
+
+            if args.is_empty() {
+                println!("info string usage: datagen <file> [games N] [depth D] [nodes N] [randomplies R] [seed S]");
+            }else {
+                let option = |name: &str, default: usize| -> usize {
+                    match args.iter().position(|arg| *arg == name) {
+                        Some(index) => args.get(index + 1).and_then(|val| val.parse::<usize>().ok()).unwrap_or(default),
+                        None => default,
+                    }
+                };
+
+                let config = DatagenConfig {
+                    games: option("games", 100),
+                    depth: option("depth", 6),
+                    nodes: option("nodes", 0),
+                    random_plies: option("randomplies", 8),
+                    seed: option("seed", 1) as u32,
+                };
+
+                datagen(args[0], config, char_pieces, ht);
+            }
         }else if input.chars().take(1).collect::<Vec<char>>().iter().collect::<String>() == "d" {
             print_board();
         }else if input.chars().take(8).collect::<Vec<char>>().iter().collect::<String>() == "evaluate" {
             println!("static evaluation: {}", evaluate());
+        }else if input.chars().take(4).collect::<Vec<char>>().iter().collect::<String>() == "eval" {
+            print_evaluation();
+        }else if input.chars().take(4).collect::<Vec<char>>().iter().collect::<String>() == "tune" {
+            // tune <dataset> <output> [iterations]
+            let args = input.split_whitespace().skip(1).collect::<Vec<&str>>();
+
+            if args.len() < 2 {
+                println!("info string usage: tune <dataset> <output> [iterations]");
+            }else {
+                let iterations = match args.get(2) {
+                    Some(val) => val.parse::<usize>().unwrap_or(TUNE_ITERATIONS),
+                    None => TUNE_ITERATIONS,
+                };
+
+                tune(args[0], args[1], iterations, char_pieces);
+            }
+        }else if input.chars().take(3).collect::<Vec<char>>().iter().collect::<String>() == "pgn" {
+            // pgn <input> <output>
+            let args = input.split_whitespace().skip(1).collect::<Vec<&str>>();
+
+            if args.len() < 2 {
+                println!("info string usage: pgn <input> <output>");
+            }else {
+                pgn::convert_games(args[0], args[1], char_pieces);
+            }
+        }else if input.chars().take(5).collect::<Vec<char>>().iter().collect::<String>() == "match" {
+            // match <engine1> <engine2> [games N] [openings FILE] [tc BASE+INC] [movetime MS] [nodes N] [depth D] [pgn FILE] [sprt ELO0 ELO1] [alpha A] [beta B]
+            match tournament::parse_settings(&input.split_whitespace().skip(1).collect::<Vec<&str>>()) {
+                Ok(settings) => tournament::run_match(&settings, char_pieces),
+                Err(e) => println!("info string {}", e),
+            }
+        }else if input.chars().take(4).collect::<Vec<char>>().iter().collect::<String>() == "sprt" {
+            // sprt <elo0> <elo1> <alpha> <beta> <losses> <draws> <wins> | <p0> <p1> <p2> <p3> <p4>
+            sprt::run(&input.split_whitespace().skip(1).collect::<Vec<&str>>());
+        }else if input.chars().take(8).collect::<Vec<char>>().iter().collect::<String>() == "makebook" {
+            // makebook <pgn> <output> [plies N]
+            let args = input.split_whitespace().skip(1).collect::<Vec<&str>>();
+
+            if args.len() < 2 {
+                println!("info string usage: makebook <pgn> <output> [plies N]");
+            }else {
+                let plies = match args.iter().position(|arg| *arg == "plies") {
+                    Some(index) => args.get(index + 1).and_then(|val| val.parse::<usize>().ok()).unwrap_or(polyglot::BOOK_PLIES),
+                    None => polyglot::BOOK_PLIES,
+                };
+
+                polyglot::make_book(args[0], args[1], plies, char_pieces);
+            }
+        }else if input.chars().take(5).collect::<Vec<char>>().iter().collect::<String>() == "bench" {
+            // bench [depth]
+            let bench_depth = input.chars().skip(6).collect::<Vec<char>>().iter().collect::<String>();
+
+            match bench_depth.trim() {
+                "" => bench::bench(bench::BENCH_DEPTH, char_pieces, ht),
+                depth => match depth.parse::<usize>() {
+                    Ok(val) if val > 0 => bench::bench(val, char_pieces, ht),
+                    _ => println!("info string unknown value for depth: {}", depth),
+                },
+            }
+        }else if input == "stats" {
+            // statistics of the last search
+            #[cfg(feature = "stats")]
+            stats::print();
+            #[cfg(not(feature = "stats"))]
+            println!("info string search statistics need the stats feature");
+        }else if input.chars().take(3).collect::<Vec<char>>().iter().collect::<String>() == "epd" {
+            // epd <file> [depth D] [movetime MS]
+            epd::run(&input.split_whitespace().skip(1).collect::<Vec<&str>>(), char_pieces, ht);
         }
 
         input.clear();
@@ -4443,7 +6494,32 @@ fn init_all(char_pieces: &mut HashMap<char, u32>) {
     init_sliders_table(1);
     init_sliders_table(0);
     init_random_keys();
-    init_evaluation_masks()
+    init_evaluation_masks();
+    init_piece_square_scores();
+    init_lmr_table();
+    endgame::init_kpk_bitbase();
+}
+
+// the board & lookup tables are globals: tests using them run one at a time
+#[cfg(test)]
+static TEST_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+#[cfg(test)]
+fn init_test() -> (std::sync::MutexGuard<'static, ()>, HashMap<char, u32>) {
+    static INIT: std::sync::Once = std::sync::Once::new();
+
+    let guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut char_pieces = HashMap::new();
+    INIT.call_once(|| init_all(&mut HashMap::new()));
+    init_char_pieces(&mut char_pieces);
+
+    // searches never poll stdin
+    unsafe {
+        LISTEN_INPUT = false;
+    }
+
+    (guard, char_pieces)
 }
 
 
@@ -4456,14 +6532,343 @@ fn main() {
 
     init_all(&mut char_pieces);
 
-    let debug = false;
+    // evaluation parameters given by the environment
+    if let Ok(path) = std::env::var("CHENG_EVAL_FILE") {
+        if let Err(e) = load_eval_file(&path) {
+            println!("info string failed to load evaluation: {}", e);
+        }
+    }
 
-    if debug {
-        parse_fen("rn2kb1r/p3pppp/1p2b3/3q4/R2PP3/1p3Q2/5PPP/1NB1KB1R b Kkq - 0 12", &char_pieces);
-        print_board();
-        search_position(13, &mut ht);
-    }else {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    // UCI mode unless a subcommand is given
+    if args.is_empty() {
         uci_loop(&char_pieces, &mut ht);
+    }else {
+        std::process::exit(cli::run(&args, &char_pieces, &mut ht));
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // en passant captures that expose the king (published perft 6 counts)
+    #[test]
+    fn perft_enpassant_pins() {
+        let (_lock, char_pieces) = init_test();
+
+        for (fen, nodes) in [
+            // the captured pawn leaves the rank of the king & the rook
+            ("3k4/3p4/8/K1P4r/8/8/8/8 b - - 0 1", 1134888),
+            ("8/8/4k3/8/2p5/8/B2P2K1/8 w - - 0 1", 1015133),
+            ("8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1", 1440467),
+        ] {
+            parse_fen(fen, &char_pieces);
+
+            assert_eq!(perft_driver(6, false), nodes, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn quiescence_counts_promotions_in_delta_pruning() {
+        let (_lock, char_pieces) = init_test();
+        let mut ht = HashMap::new();
+
+        // bxc8=Q wins a queen & promotes
+        parse_fen("2q5/1P5k/8/8/8/8/6rq/K7 w - - 0 1", &char_pieces);
+        reset_time_control();
+
+        let evaluation = evaluate();
+
+        // out of reach of a queen capture alone but not of a capture promotion
+        let alpha = evaluation + MATERIAL_SCORE[Piece::Q as usize] + DELTA_MARGIN + 100;
+
+        assert_eq!(quiescence(alpha, alpha + 1, &mut ht), alpha + 1);
+    }
+
+    #[test]
+    fn lmr_table_covers_every_ply_and_move() {
+        let _test = init_test();
+
+        let table = unsafe { LMR_TABLE };
+
+        // the first move & the shallowest depths are never reduced
+        assert!(table.iter().all(|reductions| reductions[1] == 0));
+        assert!(table[1].iter().all(|&reduction| reduction == 0));
+
+        for depth in 2..MAX_PLY {
+            for moves_searched in 2..MAX_MOVES {
+                assert!(table[depth][moves_searched] >= table[depth - 1][moves_searched]);
+                assert!(table[depth][moves_searched] >= table[depth][moves_searched - 1]);
+            }
+        }
+
+        assert!(table[MAX_PLY - 1][MAX_MOVES - 1] > table[8][8]);
+    }
+
+    // the same position with colors swapped & the board flipped
+    fn mirror_fen(fen: &str) -> String {
+        let fields = fen.split_whitespace().collect::<Vec<&str>>();
+
+        let swap_case = |text: &str| text.chars().map(|c| if c.is_ascii_uppercase() { c.to_ascii_lowercase() } else { c.to_ascii_uppercase() }).collect::<String>();
+
+        let board = fields[0].split('/').rev().map(swap_case).collect::<Vec<String>>().join("/");
+        let side = if fields[1] == "w" { "b" } else { "w" };
+        let castling = if fields[2] == "-" { String::from("-") } else { swap_case(fields[2]) };
+        let enpassant = match fields[3].as_bytes() {
+            [file, rank] => format!("{}{}", *file as char, (b'1' + b'8' - rank) as char),
+            _ => String::from("-"),
+        };
+
+        format!("{} {} {} {} {}", board, side, castling, enpassant, fields[4..].join(" "))
+    }
+
+    #[test]
+    fn evaluation_is_color_symmetric() {
+        let (_lock, char_pieces) = init_test();
+
+        for fen in [START_POSTITION, TRICKY_POSITION, KILLER_POSITION, CMK_POSITION, "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 11",
+            "4rrk1/pp1n3p/3q2pQ/2p1pb2/2PP4/2P3N1/P2B2PP/4RRK1 b - - 7 19", "6k1/6p1/6Pp/ppp5/3pn2P/1P3K2/1PP2P2/3N4 b - - 0 1"] {
+            parse_fen(fen, &char_pieces);
+            let score = evaluate();
+
+            parse_fen(&mirror_fen(fen), &char_pieces);
+
+            assert_eq!(evaluate(), score, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn evaluation_breakdown_adds_up_to_the_evaluation() {
+        let (_lock, char_pieces) = init_test();
+
+        let (white, black) = (PieceColor::WHITE as usize, PieceColor::BLACK as usize);
+
+        for fen in [START_POSTITION, TRICKY_POSITION, KILLER_POSITION, CMK_POSITION, "3b4/5kp1/1p1p1p1p/pP1PpP1P/P1P1P3/3KN3/8/8 w - - 0 1"] {
+            parse_fen(fen, &char_pieces);
+
+            let eval_info = init_eval_info();
+
+            // terms as printed by the eval command
+            let mut total = material_score(white) - material_score(black) + positional_score(white) - positional_score(black);
+
+            for (_, evaluate_term) in EVAL_TERMS.iter() {
+                total += evaluate_term(white, &eval_info) - evaluate_term(black, &eval_info);
+            }
+
+            let score = endgame::scale(total, endgame::scale_factor()).taper(unsafe { PHASE_SCORE });
+
+            assert_eq!(if unsafe { SIDE } == white as i32 { score } else { -score }, evaluate(), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn eval_params_text_round_trip() {
+        let mut params = DEFAULT_EVAL_PARAMS;
+
+        params.material[GamePhase::ENDGAME as usize][Piece::N as usize] = 321;
+        params.material[GamePhase::ENDGAME as usize][Piece::n as usize] = -321;
+        params.positional[GamePhase::OPENING as usize][Piece::B as usize][10] = 77;
+        params.passed_pawn_bonus[6] = Score::new(-5, 140);
+        params.bishop_pair = Score::new(31, 59);
+        params.king_attack_weights[Piece::R as usize] = 9;
+
+        let text = params.to_text();
+        let parsed = EvalParams::from_text(&text).unwrap();
+
+        assert_eq!(parsed.to_text(), text);
+        assert_eq!(parsed.material, params.material);
+        assert_eq!(parsed.positional, params.positional);
+        assert_eq!(parsed.bishop_pair, params.bishop_pair);
+
+        // missing weights keep their default, unknown or short ones are errors
+        assert_eq!(EvalParams::from_text("# empty\n").unwrap().to_text(), DEFAULT_EVAL_PARAMS.to_text());
+        assert!(EvalParams::from_text("bishop_pair 1 2 3").is_err());
+        assert!(EvalParams::from_text("no_such_weight 1").is_err());
+        assert!(EvalParams::from_text("bishop_pair 1 x").is_err());
+    }
+
+    #[test]
+    fn tuning_lines() {
+        let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+
+        for (line, result) in [
+            (format!("{} | 20 | 1.0", fen), 1.0),
+            (format!("{} 1-0", fen), 1.0),
+            (format!("{} [0.5]", fen), 0.5),
+            (format!("{} c9 \"0-1\";", fen), 0.0),
+            (format!("{} 1/2-1/2", fen), 0.5),
+        ] {
+            assert_eq!(parse_tuning_line(&line), Some((fen.to_string(), result)), "{}", line);
+        }
+
+        assert_eq!(parse_tuning_line(fen), None);
+        assert_eq!(parse_tuning_line("8/8 w 1-0"), None);
+    }
+
+    #[test]
+    fn tune_skips_invalid_lines() {
+        let (_lock, char_pieces) = init_test();
+
+        let directory = std::env::temp_dir();
+        let dataset = directory.join("cheng_tune_dataset.txt");
+        let output = directory.join("cheng_tune_output.txt");
+
+        std::fs::write(&dataset, "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1 0.5\n\
+            rnbqkbnr/ppppXppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 1-0\n\
+            8/8/8/8/8/8/8/8 w - - 0 1 0-1\n\
+            8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 11 0-1\n").unwrap();
+
+        let params = unsafe { EVAL_PARAMS }.to_text();
+
+        tune(dataset.to_str().unwrap(), output.to_str().unwrap(), 1, &char_pieces);
+
+        // tuned weights are written out, the ones in use are kept
+        assert!(EvalParams::from_text(&std::fs::read_to_string(&output).unwrap()).is_ok());
+        assert_eq!(unsafe { EVAL_PARAMS }.to_text(), params);
+
+        let _ = std::fs::remove_file(dataset);
+        let _ = std::fs::remove_file(output);
+    }
+
+    #[test]
+    fn datagen_is_deterministic() {
+        let (_lock, char_pieces) = init_test();
+        let mut ht = HashMap::new();
+
+        let path = std::env::temp_dir().join("cheng_datagen.txt");
+        let path = path.to_str().unwrap();
+
+        let mut generate = |seed: u32| {
+            datagen(path, DatagenConfig { games: 2, depth: 2, nodes: 0, random_plies: 6, seed }, &char_pieces, &mut ht);
+
+            std::fs::read_to_string(path).unwrap()
+        };
+
+        let data = generate(7);
+
+        assert!(!data.is_empty());
+        assert_eq!(generate(7), data);
+        assert_ne!(generate(8), data);
+
+        // every line is a tuning position
+        assert!(data.lines().all(|line| parse_tuning_line(line).is_some()));
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    // incremental scores & pawn key after every make_move & take_back of a short walk against a recomputation
+    fn assert_incremental_walk(depth: usize) {
+        if depth == 0 {
+            return;
+        }
+
+        for mv in generate_moves() {
+            let board_copy = copy_board();
+
+            make_move(mv, MOVE_TYPE::all_moves);
+
+            let (eval_score, phase_score, pawn_key) = unsafe { (EVAL_SCORE, PHASE_SCORE, PAWN_KEY) };
+
+            assert_eq!(eval_score, compute_eval_score(), "eval after {} in {}", get_uci_move(mv), get_fen());
+            assert_eq!(phase_score, get_game_phase_score(), "phase after {} in {}", get_uci_move(mv), get_fen());
+            assert_eq!(pawn_key, generate_pawn_key(), "pawn key after {} in {}", get_uci_move(mv), get_fen());
+
+            assert_incremental_walk(depth - 1);
+
+            take_back(board_copy);
+        }
+    }
+
+    #[test]
+    fn incremental_state_matches_recomputation() {
+        let (_lock, char_pieces) = init_test();
+
+        // castling, en passant, promotions & captures of promoted pieces
+        for fen in [START_POSTITION, TRICKY_POSITION, KILLER_POSITION, "r3k2r/1P6/8/2pP4/8/8/6p1/R3K2R w KQkq c6 0 1"] {
+            parse_fen(fen, &char_pieces);
+
+            let root = unsafe { (EVAL_SCORE, PHASE_SCORE, PAWN_KEY) };
+
+            assert_incremental_walk(3);
+
+            // take_back restores the scores & the pawn key of the root
+            assert_eq!(unsafe { (EVAL_SCORE, PHASE_SCORE, PAWN_KEY) }, root, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn score_packing_and_taper() {
+        let values = [0, 1, -1, 250, -250, 32767, -32768, 1000, -1000];
+
+        for opening in values {
+            for endgame in values {
+                let score = Score::new(opening, endgame);
+
+                assert_eq!((score.opening(), score.endgame()), (opening, endgame));
+            }
+        }
+
+        // arithmetic on the packed value works on both halves, borrows included
+        let a = Score::new(-300, 120);
+        let b = Score::new(45, -500);
+
+        assert_eq!(a + b, Score::new(-255, -380));
+        assert_eq!(a - b, Score::new(-345, 620));
+        assert_eq!(-a, Score::new(300, -120));
+        assert_eq!(b * -3, Score::new(-135, 1500));
+
+        let mut c = a;
+        c += b;
+        c -= a;
+        assert_eq!(c, b);
+
+        // full opening & endgame phases, clamped outside of them
+        let score = Score::new(100, -60);
+
+        assert_eq!(score.taper(OPENING_PHASE_SCORE), 100);
+        assert_eq!(score.taper(OPENING_PHASE_SCORE + 1000), 100);
+        assert_eq!(score.taper(ENDGAME_PHASE_SCORE), -60);
+        assert_eq!(score.taper(0), -60);
+        assert_eq!(score.taper((OPENING_PHASE_SCORE + ENDGAME_PHASE_SCORE) / 2), 20);
+
+        // the taper moves one way between the phases
+        let tapered = (ENDGAME_PHASE_SCORE..=OPENING_PHASE_SCORE).map(|phase| score.taper(phase)).collect::<Vec<i32>>();
+        assert!(tapered.windows(2).all(|pair| pair[0] <= pair[1]));
     }
 
+    #[test]
+    fn fen_round_trip() {
+        let (_lock, char_pieces) = init_test();
+
+        for fen in [
+            START_POSTITION.trim(),
+            TRICKY_POSITION.trim(),
+            KILLER_POSITION,
+            "r3k2r/8/8/8/8/8/8/R3K2R b Kq - 0 1",
+            "8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1",
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+        ] {
+            parse_fen(fen, &char_pieces);
+            assert_eq!(get_fen(), fen);
+
+            // positions reached by moves set up the same board & hash key again
+            for mv in generate_moves() {
+                let board_copy = copy_board();
+
+                make_move(mv, MOVE_TYPE::all_moves);
+
+                let (fen_after, hash_key) = (get_fen(), unsafe { HASH_KEY });
+
+                parse_fen(&fen_after, &char_pieces);
+
+                assert_eq!(get_fen(), fen_after);
+                assert_eq!(unsafe { HASH_KEY }, hash_key, "{}", fen_after);
+
+                take_back(board_copy);
+            }
+        }
+    }
 }
@@ -0,0 +1,476 @@
+/*
+    NNUE evaluation (enabled with the "nnue" cargo feature)
+
+    Architecture: 768 inputs (color x piece x square) -> HIDDEN_SIZE
+    accumulator neurons per perspective -> clipped ReLU -> 1 output.
+
+    The side to move's accumulator & the other side's accumulator are
+    concatenated before the output layer. Black's perspective sees the
+    board with colors swapped and ranks mirrored, so one set of feature
+    weights serves both sides.
+
+    Network file (all values little-endian i16, quantised):
+        feature weights   [768][HIDDEN_SIZE]
+        feature biases    [HIDDEN_SIZE]
+        output weights    [2][HIDDEN_SIZE]   (side to move first)
+        output bias       [1]
+
+    Input index for a piece of color c & type t (P..K = 0..5) on square sq
+    (a1 = 0) is c * 384 + t * 64 + sq from white's perspective.
+
+    Accumulators are updated incrementally from make_move (through
+    add_piece_score/remove_piece_score) & saved in BoardCopy.
+
+    Accumulator updates & the output layer have two paths giving the same
+    results: a scalar path working one neuron at a time & a SIMD path
+    working on blocks of LANES neurons (i32 sums per lane in the output
+    layer), which the compiler auto-vectorises. On x86_64 CPUs with AVX2
+    the SIMD path is compiled for AVX2 & picked at run time, otherwise
+    the scalar path is used (build with RUSTFLAGS="-C target-cpu=native"
+    to vectorise it for the build machine too).
+*/
+
+use super::*;
+
+// accumulator neurons per perspective
+pub const HIDDEN_SIZE: usize = 256;
+
+// number of input features
+const INPUT_SIZE: usize = 768;
+
+// quantisation of the accumulator & output weights
+const QA: i32 = 255;
+const QB: i32 = 64;
+
+// network output to centipawns
+const SCALE: i32 = 400;
+
+// neurons per SIMD block (16 x i16 fill a 256 bit register)
+const LANES: usize = 16;
+
+// accumulated hidden layer [perspective][neuron]
+#[derive(Copy, Clone, PartialEq)]
+pub struct Accumulator {
+    values: [[i16; HIDDEN_SIZE]; 2],
+}
+
+struct Network {
+    // [feature][neuron]
+    feature_weights: Vec<[i16; HIDDEN_SIZE]>,
+    feature_biases: [i16; HIDDEN_SIZE],
+    // [side to move / other side][neuron]
+    output_weights: [[i16; HIDDEN_SIZE]; 2],
+    output_bias: i16,
+}
+
+// loaded network
+static mut NETWORK: Option<Box<Network>> = None;
+
+// accumulator of the current position
+pub static mut ACCUMULATOR: Accumulator = Accumulator { values: [[0; HIDDEN_SIZE]; 2] };
+
+// evaluate with the network when one is loaded ("UseNNUE" option)
+pub static mut USE_NNUE: bool = true;
+
+// SIMD path in use, detected when a network is loaded
+static mut USE_SIMD: bool = false;
+
+// can this CPU run the SIMD path
+fn simd_available() -> bool {
+    #[cfg(target_arch = "x86_64")]
+    {
+        is_x86_feature_detected!("avx2")
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        false
+    }
+}
+
+/*
+    Scalar path
+*/
+
+// add (or subtract) feature weights to accumulator values
+fn update_scalar(values: &mut [i16; HIDDEN_SIZE], weights: &[i16; HIDDEN_SIZE], add: bool) {
+    for (value, weight) in values.iter_mut().zip(weights.iter()) {
+        *value = if add { value.wrapping_add(*weight) } else { value.wrapping_sub(*weight) };
+    }
+}
+
+// clipped ReLU of the accumulator values times the output weights
+fn dot_scalar(values: &[i16; HIDDEN_SIZE], weights: &[i16; HIDDEN_SIZE]) -> i64 {
+    let mut sum: i64 = 0;
+
+    for (value, weight) in values.iter().zip(weights.iter()) {
+        sum += (*value as i32).clamp(0, QA) as i64 * *weight as i64;
+    }
+
+    sum
+}
+
+/*
+    SIMD path
+*/
+
+#[inline(always)]
+fn update_lanes(values: &mut [i16; HIDDEN_SIZE], weights: &[i16; HIDDEN_SIZE], add: bool) {
+    for (values, weights) in values.chunks_exact_mut(LANES).zip(weights.chunks_exact(LANES)) {
+        if add {
+            values.iter_mut().zip(weights).for_each(|(value, weight)| *value = value.wrapping_add(*weight));
+        }else {
+            values.iter_mut().zip(weights).for_each(|(value, weight)| *value = value.wrapping_sub(*weight));
+        }
+    }
+}
+
+// a lane sums HIDDEN_SIZE / LANES products of at most QA * i16::MAX, far from overflowing an i32
+#[inline(always)]
+fn dot_lanes(values: &[i16; HIDDEN_SIZE], weights: &[i16; HIDDEN_SIZE]) -> i64 {
+    let mut sums = [0i32; LANES];
+
+    for (values, weights) in values.chunks_exact(LANES).zip(weights.chunks_exact(LANES)) {
+        for ((sum, value), weight) in sums.iter_mut().zip(values).zip(weights) {
+            *sum += (*value as i32).clamp(0, QA) * *weight as i32;
+        }
+    }
+
+    sums.iter().map(|&sum| sum as i64).sum()
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+fn update_avx2(values: &mut [i16; HIDDEN_SIZE], weights: &[i16; HIDDEN_SIZE], add: bool) {
+    update_lanes(values, weights, add);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+fn dot_avx2(values: &[i16; HIDDEN_SIZE], weights: &[i16; HIDDEN_SIZE]) -> i64 {
+    dot_lanes(values, weights)
+}
+
+fn update_simd(values: &mut [i16; HIDDEN_SIZE], weights: &[i16; HIDDEN_SIZE], add: bool) {
+    // only called after simd_available() found AVX2
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        update_avx2(values, weights, add);
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    update_lanes(values, weights, add);
+}
+
+fn dot_simd(values: &[i16; HIDDEN_SIZE], weights: &[i16; HIDDEN_SIZE]) -> i64 {
+    // only called after simd_available() found AVX2
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        dot_avx2(values, weights)
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    dot_lanes(values, weights)
+}
+
+// update accumulator values on the path in use
+fn update(values: &mut [i16; HIDDEN_SIZE], weights: &[i16; HIDDEN_SIZE], add: bool) {
+    if unsafe { USE_SIMD } {
+        update_simd(values, weights, add);
+    }else {
+        update_scalar(values, weights, add);
+    }
+}
+
+// output layer sum on the path in use
+fn dot(values: &[i16; HIDDEN_SIZE], weights: &[i16; HIDDEN_SIZE]) -> i64 {
+    if unsafe { USE_SIMD } {
+        dot_simd(values, weights)
+    }else {
+        dot_scalar(values, weights)
+    }
+}
+
+fn network() -> Option<&'static Network> {
+    unsafe { (*std::ptr::addr_of!(NETWORK)).as_deref() }
+}
+
+// input feature of a piece on a square seen from a perspective
+fn feature_index(perspective: usize, piece: usize, square: usize) -> usize {
+    let color = piece / 6;
+    let piece_type = piece % 6;
+
+    if perspective == PieceColor::WHITE as usize {
+        color * 384 + piece_type * 64 + square
+    }else {
+        (color ^ 1) * 384 + piece_type * 64 + (square ^ 56)
+    }
+}
+
+// update the accumulator when a piece is put on a square
+pub fn add_feature(piece: usize, square: usize) {
+    if let Some(network) = network() {
+        unsafe {
+            let accumulator = &mut *std::ptr::addr_of_mut!(ACCUMULATOR);
+
+            for perspective in 0..2 {
+                update(&mut accumulator.values[perspective], &network.feature_weights[feature_index(perspective, piece, square)], true);
+            }
+        }
+    }
+}
+
+// update the accumulator when a piece is removed from a square
+pub fn remove_feature(piece: usize, square: usize) {
+    if let Some(network) = network() {
+        unsafe {
+            let accumulator = &mut *std::ptr::addr_of_mut!(ACCUMULATOR);
+
+            for perspective in 0..2 {
+                update(&mut accumulator.values[perspective], &network.feature_weights[feature_index(perspective, piece, square)], false);
+            }
+        }
+    }
+}
+
+// compute the accumulator of the current position from scratch
+fn compute_accumulator(network: &Network) -> Accumulator {
+    let mut accumulator = Accumulator { values: [network.feature_biases; 2] };
+
+    let bitboards = unsafe { PIECE_BITBOARDS };
+
+    for (piece, mut bitboard) in bitboards.into_iter().enumerate() {
+
+        while bitboard != 0 {
+            let square = match index_lsb(bitboard) {
+                Ok(val) => val,
+                Err(e) => panic!("invalid bitboard: {:?}", e),
+            };
+
+            for perspective in 0..2 {
+                update(&mut accumulator.values[perspective], &network.feature_weights[feature_index(perspective, piece, square)], true);
+            }
+
+            reset_bit!(bitboard, square);
+        }
+    }
+
+    accumulator
+}
+
+// reset the accumulator for the current position (new position or network)
+pub fn refresh_accumulator() {
+    if let Some(network) = network() {
+        unsafe {
+            ACCUMULATOR = compute_accumulator(network);
+        }
+    }
+}
+
+// is the network used for evaluation
+pub fn is_active() -> bool {
+    unsafe { USE_NNUE && network().is_some() }
+}
+
+// network evaluation relative to the side to move (None without a network)
+pub fn evaluate() -> Option<i32> {
+    if !is_active() {
+        return None;
+    }
+
+    let network = network()?;
+
+    let (accumulator, side) = unsafe { (ACCUMULATOR, SIDE as usize) };
+
+    // incremental accumulator must match the full recomputation
+    debug_assert!(accumulator == compute_accumulator(network), "incremental nnue accumulator out of sync");
+
+    // 2 * HIDDEN_SIZE products of up to QA * i16::MAX overflow an i32
+    let mut output: i64 = 0;
+
+    for (perspective, weights) in [side, side ^ 1].into_iter().zip(network.output_weights.iter()) {
+        output += dot(&accumulator.values[perspective], weights);
+    }
+
+    output += network.output_bias as i64 * QA as i64;
+
+    // keep network scores clear of mate scores
+    let score = output * SCALE as i64 / (QA * QB) as i64;
+
+    Some(score.clamp(-(MATE_SCORE as i64) + 1, MATE_SCORE as i64 - 1) as i32)
+}
+
+// back to the classical evaluation
+pub fn unload_network() {
+    unsafe {
+        NETWORK = None;
+    }
+}
+
+// load network weights from a file
+pub fn load_network(path: &str) -> Result<(), String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+
+    let expected_size = (INPUT_SIZE * HIDDEN_SIZE + HIDDEN_SIZE + 2 * HIDDEN_SIZE + 1) * 2;
+
+    if bytes.len() != expected_size {
+        return Err(format!("{}: expected {} bytes, got {}", path, expected_size, bytes.len()));
+    }
+
+    let mut values = bytes.chunks_exact(2).map(|pair| i16::from_le_bytes([pair[0], pair[1]]));
+
+    let mut network = Box::new(Network {
+        feature_weights: vec![[0; HIDDEN_SIZE]; INPUT_SIZE],
+        feature_biases: [0; HIDDEN_SIZE],
+        output_weights: [[0; HIDDEN_SIZE]; 2],
+        output_bias: 0,
+    });
+
+    for weights in network.feature_weights.iter_mut() {
+        weights.iter_mut().for_each(|weight| *weight = values.next().unwrap_or_default());
+    }
+
+    network.feature_biases.iter_mut().for_each(|bias| *bias = values.next().unwrap_or_default());
+
+    for weights in network.output_weights.iter_mut() {
+        weights.iter_mut().for_each(|weight| *weight = values.next().unwrap_or_default());
+    }
+
+    network.output_bias = values.next().unwrap_or_default();
+
+    set_network(network);
+
+    Ok(())
+}
+
+fn set_network(network: Box<Network>) {
+    unsafe {
+        NETWORK = Some(network);
+        USE_SIMD = simd_available();
+    }
+
+    refresh_accumulator();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // pseudo random values in [-range, range)
+    fn random_values<const N: usize>(seed: &mut u64, range: i32) -> [i16; N] {
+        std::array::from_fn(|_| {
+            *seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((*seed >> 33) as i32 % range) as i16
+        })
+    }
+
+    // small hand-built network, unloaded when the test ends
+    struct TestNetwork;
+
+    impl TestNetwork {
+        fn load() -> TestNetwork {
+            let mut seed = 1;
+
+            let network = Box::new(Network {
+                feature_weights: (0..INPUT_SIZE).map(|_| random_values(&mut seed, 64)).collect(),
+                feature_biases: random_values(&mut seed, 128),
+                output_weights: [random_values(&mut seed, 64), random_values(&mut seed, 64)],
+                output_bias: 25,
+            });
+
+            set_network(network);
+
+            TestNetwork
+        }
+    }
+
+    impl Drop for TestNetwork {
+        fn drop(&mut self) {
+            unload_network();
+        }
+    }
+
+    // accumulator after every make_move & take_back of a short walk against a refresh
+    fn walk(depth: usize, network: &Network) {
+        if depth == 0 {
+            return;
+        }
+
+        for mv in generate_moves() {
+            let board_copy = copy_board();
+
+            make_move(mv, MOVE_TYPE::all_moves);
+            assert!(unsafe { ACCUMULATOR } == compute_accumulator(network), "after {}", get_uci_move(mv));
+
+            walk(depth - 1, network);
+
+            take_back(board_copy);
+            assert!(unsafe { ACCUMULATOR } == compute_accumulator(network), "after taking back {}", get_uci_move(mv));
+        }
+    }
+
+    #[test]
+    fn incremental_accumulator_matches_refresh() {
+        let (_lock, char_pieces) = init_test();
+        let _network = TestNetwork::load();
+
+        // castling, en passant & promotions are all covered
+        for fen in [START_POSTITION, TRICKY_POSITION, KILLER_POSITION, "r3k2r/1P6/8/2pP4/8/8/6p1/R3K2R w KQkq c6 0 1"] {
+            parse_fen(fen, &char_pieces);
+
+            walk(2, network().unwrap());
+        }
+    }
+
+    #[test]
+    fn simd_path_matches_scalar() {
+        let (_lock, char_pieces) = init_test();
+
+        let mut seed = 7;
+
+        for _ in 0..100 {
+            let values: [i16; HIDDEN_SIZE] = random_values(&mut seed, i16::MAX as i32);
+            let weights: [i16; HIDDEN_SIZE] = random_values(&mut seed, i16::MAX as i32);
+
+            let (mut scalar, mut lanes) = (values, values);
+
+            for add in [true, false] {
+                update_scalar(&mut scalar, &weights, add);
+                update_lanes(&mut lanes, &weights, add);
+                assert!(scalar == lanes);
+            }
+
+            assert_eq!(dot_scalar(&values, &weights), dot_lanes(&values, &weights));
+
+            if simd_available() {
+                update_simd(&mut lanes, &weights, true);
+                update_scalar(&mut scalar, &weights, true);
+                assert!(scalar == lanes);
+
+                assert_eq!(dot_scalar(&values, &weights), dot_simd(&values, &weights));
+            }
+        }
+
+        // largest products on every neuron
+        assert_eq!(dot_lanes(&[i16::MAX; HIDDEN_SIZE], &[i16::MIN; HIDDEN_SIZE]), QA as i64 * i16::MIN as i64 * HIDDEN_SIZE as i64);
+
+        // both paths evaluate alike
+        let _network = TestNetwork::load();
+
+        for fen in [START_POSTITION, TRICKY_POSITION, KILLER_POSITION, CMK_POSITION] {
+            parse_fen(fen, &char_pieces);
+
+            let scores = [false, true].map(|simd| {
+                unsafe {
+                    USE_SIMD = simd && simd_available();
+                }
+
+                refresh_accumulator();
+                evaluate()
+            });
+
+            assert!(scores[0].is_some());
+            assert_eq!(scores[0], scores[1], "{}", fen);
+        }
+    }
+}
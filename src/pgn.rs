@@ -0,0 +1,776 @@
+/*
+    PGN games & SAN moves
+
+    A game keeps its tag pairs, the main line & the result. Every move
+    of a line carries its SAN, NAGs ($n), the comments ({...} or ;...)
+    written before & after it and the variations ((...)) that replace it.
+
+    read_games splits a PGN database into games, write_game prints a game
+    back as PGN (move numbers are taken from the FEN tag when present).
+
+    move_to_san & parse_san convert between the move encoding & SAN
+    (e4, Nbd7, exd8=Q+, O-O, Qh4#) in the current position.
+
+    The "pgn" command replays every game of a file & writes it back with
+    its SAN regenerated from the moves.
+*/
+
+use super::*;
+
+// longest movetext line written
+const LINE_WIDTH: usize = 80;
+
+// game termination markers
+static RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+#[derive(Clone, Default)]
+pub struct PgnMove {
+    pub san: String,
+    pub nags: Vec<u32>,
+    pub comment_before: Option<String>,
+    pub comment_after: Option<String>,
+    // alternatives to this move
+    pub variations: Vec<Vec<PgnMove>>,
+}
+
+impl PgnMove {
+    pub fn new(san: &str) -> PgnMove {
+        PgnMove { san: san.to_string(), ..Default::default() }
+    }
+}
+
+#[derive(Clone)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<PgnMove>,
+    pub result: String,
+}
+
+impl PgnGame {
+    pub fn new() -> PgnGame {
+        PgnGame { tags: Vec::new(), moves: Vec::new(), result: String::from("*") }
+    }
+
+    // value of a tag pair
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str())
+    }
+}
+
+#[cfg_attr(test, derive(PartialEq, Debug))]
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    Nag(u32),
+    VariationStart,
+    VariationEnd,
+    Result(String),
+    Move(String),
+}
+
+// tag value with the \" & \\ escapes resolved
+fn unescape(value: &str) -> String {
+    let mut text = String::new();
+    let mut chars = value.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(&next) = chars.peek() {
+                if next == '"' || next == '\\' {
+                    text.push(next);
+                    chars.next();
+                    continue;
+                }
+            }
+        }
+        text.push(c);
+    }
+
+    text
+}
+
+// split PGN text into tokens
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => {
+                // comments do not nest
+                let comment: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                tokens.push(Token::Comment(comment.split_whitespace().collect::<Vec<&str>>().join(" ")));
+            },
+            ';' => {
+                let comment: String = chars.by_ref().take_while(|&c| c != '\n').collect();
+                tokens.push(Token::Comment(comment.trim().to_string()));
+            },
+            '(' => tokens.push(Token::VariationStart),
+            ')' => tokens.push(Token::VariationEnd),
+            '[' => {
+                let mut tag = String::new();
+                let mut quoted = false;
+                let mut escaped = false;
+
+                // "]" inside a quoted value belongs to the value, \" doesn't end it
+                for c in chars.by_ref() {
+                    if c == ']' && !quoted {
+                        break;
+                    }
+                    if quoted && !escaped && c == '\\' {
+                        escaped = true;
+                    }else {
+                        if c == '"' && !escaped {
+                            quoted = !quoted;
+                        }
+                        escaped = false;
+                    }
+                    tag.push(c);
+                }
+
+                if let Some((name, value)) = tag.trim().split_once(' ') {
+                    // one pair of quotes, an escaped quote may end the value
+                    let value = value.trim();
+                    let value = value.strip_prefix('"').unwrap_or(value);
+                    let value = value.strip_suffix('"').unwrap_or(value);
+                    tokens.push(Token::Tag(name.to_string(), unescape(value)));
+                }
+            },
+            _ if c.is_whitespace() => {},
+            _ => {
+                let mut token = String::from(c);
+
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || "{}();[".contains(next) {
+                        break;
+                    }
+                    token.push(next);
+                    chars.next();
+                }
+
+                if let Some(nag) = token.strip_prefix('$') {
+                    if let Ok(val) = nag.parse::<u32>() {
+                        tokens.push(Token::Nag(val));
+                    }
+                    continue;
+                }
+
+                if RESULTS.contains(&token.as_str()) {
+                    tokens.push(Token::Result(token));
+                    continue;
+                }
+
+                // move number (12. or 12...) possibly glued to the move, digits are
+                // only stripped before a dot so castling written with zeros (0-0) stays
+                let digits = token.find(|c: char| !c.is_ascii_digit()).unwrap_or(token.len());
+
+                let san = if digits == token.len() || token[digits..].starts_with('.') {
+                    token[digits..].trim_start_matches('.')
+                }else {
+                    token.as_str()
+                };
+
+                // annotation glyphs written as suffixes
+                let (san, nag) = match ["!!", "??", "!?", "?!", "!", "?"].iter().position(|glyph| san.ends_with(glyph)) {
+                    Some(index) => (&san[..san.len() - ["!!", "??", "!?", "?!", "!", "?"][index].len()], Some([3, 4, 5, 6, 1, 2][index])),
+                    None => (san, None),
+                };
+
+                if !san.is_empty() {
+                    tokens.push(Token::Move(san.to_string()));
+                }
+
+                if let Some(val) = nag {
+                    tokens.push(Token::Nag(val));
+                }
+            },
+        }
+    }
+
+    tokens
+}
+
+// append a comment to an optional one
+pub fn add_comment(comment: &mut Option<String>, text: String) {
+    *comment = match comment.take() {
+        Some(val) => Some(format!("{} {}", val, text)),
+        None => Some(text),
+    };
+}
+
+// read a line of moves until the end of its variation, game or tokens
+fn read_line(tokens: &[Token], index: &mut usize) -> Vec<PgnMove> {
+    let mut line: Vec<PgnMove> = Vec::new();
+
+    // comment waiting for the next move
+    let mut comment_before = None;
+
+    while *index < tokens.len() {
+        match &tokens[*index] {
+            Token::Move(san) => {
+                let mut mv = PgnMove::new(san);
+                mv.comment_before = comment_before.take();
+                line.push(mv);
+            },
+            Token::Nag(nag) => {
+                if let Some(mv) = line.last_mut() {
+                    mv.nags.push(*nag);
+                }
+            },
+            Token::Comment(text) => {
+                match line.last_mut() {
+                    Some(mv) if mv.variations.is_empty() => add_comment(&mut mv.comment_after, text.clone()),
+                    _ => add_comment(&mut comment_before, text.clone()),
+                }
+            },
+            Token::VariationStart => {
+                *index += 1;
+                let variation = read_line(tokens, index);
+
+                if let Some(mv) = line.last_mut() {
+                    if !variation.is_empty() {
+                        mv.variations.push(variation);
+                    }
+                }
+            },
+            Token::VariationEnd => return line,
+            Token::Tag(_, _) | Token::Result(_) => return line,
+        }
+
+        *index += 1;
+    }
+
+    line
+}
+
+// split a PGN database into games
+pub fn read_games(text: &str) -> Vec<PgnGame> {
+    let tokens = tokenize(text);
+
+    let mut games = Vec::new();
+    let mut index = 0;
+
+    while index < tokens.len() {
+        let mut game = PgnGame::new();
+
+        while let Some(Token::Tag(name, value)) = tokens.get(index) {
+            game.tags.push((name.clone(), value.clone()));
+            index += 1;
+        }
+
+        game.moves = read_line(&tokens, &mut index);
+
+        // stray closing parentheses
+        while let Some(Token::VariationEnd) = tokens.get(index) {
+            index += 1;
+            let mut rest = read_line(&tokens, &mut index);
+            game.moves.append(&mut rest);
+        }
+
+        if let Some(Token::Result(result)) = tokens.get(index) {
+            game.result = result.clone();
+            index += 1;
+        }
+
+        if !game.tags.is_empty() || !game.moves.is_empty() {
+            games.push(game);
+        }
+    }
+
+    games
+}
+
+// write the moves of a line starting at a ply (0 = white's first move)
+fn write_line(line: &[PgnMove], first_ply: usize, words: &mut Vec<String>) {
+    // number is needed on the first move & after comments or variations
+    let mut show_number = true;
+
+    for (offset, mv) in line.iter().enumerate() {
+        let ply = first_ply + offset;
+
+        if let Some(comment) = &mv.comment_before {
+            words.push(format!("{{{}}}", comment));
+            show_number = true;
+        }
+
+        if ply.is_multiple_of(2) {
+            words.push(format!("{}. {}", ply / 2 + 1, mv.san));
+        }else if show_number {
+            words.push(format!("{}... {}", ply / 2 + 1, mv.san));
+        }else {
+            words.push(mv.san.clone());
+        }
+
+        show_number = false;
+
+        for nag in &mv.nags {
+            words.push(format!("${}", nag));
+        }
+
+        if let Some(comment) = &mv.comment_after {
+            words.push(format!("{{{}}}", comment));
+            show_number = true;
+        }
+
+        for variation in &mv.variations {
+            let mut variation_words = Vec::new();
+            write_line(variation, ply, &mut variation_words);
+
+            if let Some(first) = variation_words.first_mut() {
+                first.insert(0, '(');
+            }
+            if let Some(last) = variation_words.last_mut() {
+                last.push(')');
+            }
+
+            words.append(&mut variation_words);
+            show_number = true;
+        }
+    }
+}
+
+// game written as PGN
+pub fn write_game(game: &PgnGame) -> String {
+    let mut text = String::new();
+
+    for (name, value) in &game.tags {
+        text.push_str(&format!("[{} \"{}\"]\n", name, value.replace('\\', "\\\\").replace('"', "\\\"")));
+    }
+
+    text.push('\n');
+
+    // first ply from the side to move & move number of the FEN
+    let first_ply = match game.tag("FEN") {
+        Some(fen) => {
+            let fields = fen.split_whitespace().collect::<Vec<&str>>();
+            let move_number = fields.get(5).and_then(|val| val.parse::<usize>().ok()).unwrap_or(1).max(1);
+
+            (move_number - 1) * 2 + if fields.get(1) == Some(&"b") { 1 } else { 0 }
+        },
+        None => 0,
+    };
+
+    let mut words = Vec::new();
+    write_line(&game.moves, first_ply, &mut words);
+    words.push(game.result.clone());
+
+    // wrap movetext lines
+    let mut line_length = 0;
+
+    for word in words {
+        if line_length > 0 && line_length + 1 + word.len() > LINE_WIDTH {
+            text.push('\n');
+            line_length = 0;
+        }else if line_length > 0 {
+            text.push(' ');
+            line_length += 1;
+        }
+
+        line_length += word.len();
+        text.push_str(&word);
+    }
+
+    text.push_str("\n\n");
+
+    text
+}
+
+// SAN of a legal move in the current position
+pub fn move_to_san(mv: u64) -> String {
+    let source = get_move_source!(mv);
+    let target = get_move_target!(mv);
+    let piece = get_move_piece!(mv);
+    let promoted = get_move_promoted!(mv);
+
+    let mut san = String::new();
+
+    if get_move_castling!(mv) != 0 {
+        san.push_str(if target % 8 == 6 { "O-O" } else { "O-O-O" });
+    }else {
+        let capture = get_move_capture!(mv) != 0;
+
+        if piece % 6 == Piece::P as u64 {
+            if capture {
+                san.push((b'a' + (source % 8) as u8) as char);
+            }
+        }else {
+            san.push_str(ASCII_PIECES[(piece % 6) as usize]);
+
+            // other pieces of the same kind reaching the target
+            let others = generate_moves().into_iter()
+                .filter(|&other| get_move_piece!(other) == piece && get_move_target!(other) == target && get_move_source!(other) != source)
+                .map(|other| get_move_source!(other))
+                .collect::<Vec<u64>>();
+
+            if !others.is_empty() {
+                let same_file = others.iter().any(|&other| other % 8 == source % 8);
+                let same_rank = others.iter().any(|&other| other / 8 == source / 8);
+
+                if !same_file {
+                    san.push((b'a' + (source % 8) as u8) as char);
+                }else if !same_rank {
+                    san.push((b'1' + (source / 8) as u8) as char);
+                }else {
+                    san.push_str(SQUARE_TO_COORD[source as usize]);
+                }
+            }
+        }
+
+        if capture {
+            san.push('x');
+        }
+
+        san.push_str(SQUARE_TO_COORD[target as usize]);
+
+        if promoted != 0 {
+            san.push('=');
+            san.push_str(ASCII_PIECES[(promoted % 6) as usize]);
+        }
+    }
+
+    // check & mate suffixes
+    let board_copy = copy_board();
+    make_move(mv, MOVE_TYPE::all_moves);
+
+    if is_in_check() {
+        san.push(if generate_moves().is_empty() { '#' } else { '+' });
+    }
+
+    take_back(board_copy);
+
+    san
+}
+
+// move of the current position written in SAN (None when illegal or ambiguous)
+pub fn parse_san(san: &str) -> Option<u64> {
+    // check, mate & annotation symbols
+    let san = san.trim_end_matches(['+', '#', '!', '?']);
+
+    let side_offset = unsafe { if SIDE == PieceColor::WHITE as i32 { 0 } else { 6 } };
+
+    let legal_moves = generate_moves();
+
+    // castling (also written with zeros)
+    let castle_file = match san {
+        "O-O" | "0-0" => Some(6),
+        "O-O-O" | "0-0-0" => Some(2),
+        _ => None,
+    };
+
+    if let Some(file) = castle_file {
+        return legal_moves.into_iter().find(|&mv| get_move_castling!(mv) != 0 && get_move_target!(mv) % 8 == file);
+    }
+
+    let mut chars: Vec<char> = san.chars().collect();
+
+    // promotion piece (e8=Q or e8Q)
+    let mut promoted = 0;
+
+    if let Some(&last) = chars.last() {
+        if let Some(index) = "NBRQ".find(last.to_ascii_uppercase()).filter(|_| chars.len() > 2) {
+            promoted = index as u64 + 1 + side_offset;
+            chars.pop();
+
+            if chars.last() == Some(&'=') {
+                chars.pop();
+            }
+        }
+    }
+
+    // moving piece
+    let piece = match chars.first().and_then(|c| "NBRQK".find(*c)) {
+        Some(index) => {
+            chars.remove(0);
+            index as u64 + 1 + side_offset
+        },
+        None => side_offset,
+    };
+
+    chars.retain(|&c| c != 'x' && c != '-' && c != ':');
+
+    if chars.len() < 2 {
+        return None;
+    }
+
+    // target square
+    let target_file = chars[chars.len() - 2] as i32 - 'a' as i32;
+    let target_rank = chars[chars.len() - 1] as i32 - '1' as i32;
+
+    if !(0..8).contains(&target_file) || !(0..8).contains(&target_rank) {
+        return None;
+    }
+
+    let target = (target_rank * 8 + target_file) as u64;
+
+    // disambiguation by file and/or rank
+    let mut source_file = None;
+    let mut source_rank = None;
+
+    for &c in &chars[..chars.len() - 2] {
+        match c {
+            'a'..='h' => source_file = Some(c as u64 - 'a' as u64),
+            '1'..='8' => source_rank = Some(c as u64 - '1' as u64),
+            _ => return None,
+        }
+    }
+
+    let mut candidates = legal_moves.into_iter().filter(|&mv| {
+        get_move_piece!(mv) == piece
+            && get_move_target!(mv) == target
+            && get_move_promoted!(mv) == promoted
+            && get_move_castling!(mv) == 0
+            && source_file.is_none_or(|file| get_move_source!(mv) % 8 == file)
+            && source_rank.is_none_or(|rank| get_move_source!(mv) / 8 == rank)
+    });
+
+    let mv = candidates.next()?;
+
+    // ambiguous SAN
+    if candidates.next().is_some() {
+        return None;
+    }
+
+    Some(mv)
+}
+
+// replay a line from the current position rewriting its SAN, returns the number of dropped moves
+fn normalize_line(line: &mut Vec<PgnMove>) -> usize {
+    let board_copy = copy_board();
+
+    let mut dropped = 0;
+
+    for index in 0..line.len() {
+        // variations start from the position before the move they replace
+        for variation in line[index].variations.iter_mut() {
+            dropped += normalize_line(variation);
+        }
+
+        line[index].variations.retain(|variation| !variation.is_empty());
+
+        match parse_san(&line[index].san) {
+            Some(mv) => {
+                line[index].san = move_to_san(mv);
+                make_move(mv, MOVE_TYPE::all_moves);
+            },
+            None => {
+                // moves after an illegal one cannot be replayed
+                dropped += line.len() - index;
+                line.truncate(index);
+                break;
+            },
+        }
+    }
+
+    take_back(board_copy);
+
+    dropped
+}
+
+/*
+    PGN converter
+
+    pgn <input> <output>
+
+    Reads every game of the input file, checks its moves & variations
+    & writes them with standard SAN. Moves from an illegal one to the
+    end of its line are dropped, games with an invalid FEN tag are
+    skipped.
+*/
+pub fn convert_games(input: &str, output: &str, char_pieces: &HashMap<char, u32>) {
+    let text = match std::fs::read(input) {
+        Ok(val) => String::from_utf8_lossy(&val).to_string(),
+        Err(e) => {
+            println!("info string cannot read {}: {}", input, e);
+            return;
+        }
+    };
+
+    let mut games = read_games(&text);
+    let mut dropped = 0;
+    let mut invalid_fens = 0;
+
+    // games that can't be replayed from their FEN tag are left out
+    games.retain_mut(|game| {
+        let fen = game.tag("FEN").unwrap_or(START_POSTITION).to_string();
+
+        if try_parse_fen(&fen, char_pieces).is_err() {
+            invalid_fens += 1;
+            return false;
+        }
+
+        dropped += normalize_line(&mut game.moves);
+
+        true
+    });
+
+    let text = games.iter().map(write_game).collect::<String>();
+
+    if let Err(e) = std::fs::write(output, text) {
+        println!("info string cannot write {}: {}", output, e);
+        return;
+    }
+
+    if dropped > 0 {
+        println!("info string dropped {} illegal or unreadable moves", dropped);
+    }
+
+    if invalid_fens > 0 {
+        println!("info string skipped {} games with an invalid FEN tag", invalid_fens);
+    }
+
+    println!("info string wrote {} games to {}", games.len(), output);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // SAN of every legal move parses back to the same move
+    fn assert_san_round_trip(fen: &str, char_pieces: &HashMap<char, u32>) {
+        try_parse_fen(fen, char_pieces).unwrap();
+
+        for mv in generate_moves() {
+            let san = move_to_san(mv);
+            assert_eq!(parse_san(&san), Some(mv), "{} in {}", san, fen);
+        }
+    }
+
+    fn san_of(fen: &str, uci: &str, char_pieces: &HashMap<char, u32>) -> String {
+        try_parse_fen(fen, char_pieces).unwrap();
+        move_to_san(parse_move(uci).unwrap())
+    }
+
+    #[test]
+    fn tokenizer() {
+        let tokens = tokenize("[Event \"a \\\"b\\\"\"]\n1. e4 {open} e5 2.Nf3 Nc6!? 3...a6 $2 ; rest\n4. 0-0 (4. O-O-O) 0-0-0 1-0");
+
+        assert_eq!(tokens, vec![
+            Token::Tag(String::from("Event"), String::from("a \"b\"")),
+            Token::Move(String::from("e4")),
+            Token::Comment(String::from("open")),
+            Token::Move(String::from("e5")),
+            Token::Move(String::from("Nf3")),
+            Token::Move(String::from("Nc6")),
+            Token::Nag(5),
+            Token::Move(String::from("a6")),
+            Token::Nag(2),
+            Token::Comment(String::from("rest")),
+            Token::Move(String::from("0-0")),
+            Token::VariationStart,
+            Token::Move(String::from("O-O-O")),
+            Token::VariationEnd,
+            Token::Move(String::from("0-0-0")),
+            Token::Result(String::from("1-0")),
+        ]);
+    }
+
+    #[test]
+    fn tokenizer_escapes() {
+        let tokens = tokenize(r#"[Event "a \"]\" b"] [Site "c:\\"] [Round "\d"] 1. e4 *"#);
+
+        assert_eq!(tokens, vec![
+            Token::Tag(String::from("Event"), String::from(r#"a "]" b"#)),
+            Token::Tag(String::from("Site"), String::from(r"c:\")),
+            Token::Tag(String::from("Round"), String::from(r"\d")),
+            Token::Move(String::from("e4")),
+            Token::Result(String::from("*")),
+        ]);
+
+        // written tags read back the same
+        let mut game = PgnGame::new();
+        game.tags = vec![(String::from("Event"), String::from(r#"a "]" b \"#))];
+
+        assert_eq!(read_games(&write_game(&game))[0].tags, game.tags);
+    }
+
+    #[test]
+    fn san_round_trip() {
+        let (_lock, char_pieces) = init_test();
+
+        for fen in [
+            START_POSTITION,
+            TRICKY_POSITION,
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "Q1Q5/6k1/Q7/8/8/8/8/R3K2R w KQ - 0 1",
+            "7k/P1P5/8/8/8/8/2p5/K7 b - - 0 1",
+        ] {
+            assert_san_round_trip(fen, &char_pieces);
+        }
+    }
+
+    #[test]
+    fn castling() {
+        let (_lock, char_pieces) = init_test();
+
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+
+        assert_eq!(san_of(fen, "e1g1", &char_pieces), "O-O");
+        assert_eq!(san_of(fen, "e1c1", &char_pieces), "O-O-O");
+
+        parse_fen(fen, &char_pieces);
+        assert_eq!(parse_san("0-0"), parse_san("O-O"));
+        assert_eq!(parse_san("0-0-0"), parse_move("e1c1").ok());
+    }
+
+    #[test]
+    fn disambiguation() {
+        let (_lock, char_pieces) = init_test();
+
+        // file, rank & square
+        assert_eq!(san_of("4k3/8/8/8/8/8/4K3/R6R w - - 0 1", "a1d1", &char_pieces), "Rad1");
+        assert_eq!(san_of("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1", "a1a3", &char_pieces), "R1a3");
+        assert_eq!(san_of("8/7k/8/8/8/Q7/8/Q1Q1K3 w - - 0 1", "a1b2", &char_pieces), "Qa1b2");
+
+        // a pinned knight doesn't make the other one ambiguous
+        assert_eq!(san_of("4k3/8/8/8/8/2N3N1/8/4K3 w - - 0 1", "c3e2", &char_pieces), "Nce2");
+        assert_eq!(san_of("4k3/8/8/8/7b/2N3N1/8/4K3 w - - 0 1", "c3e2", &char_pieces), "Ne2");
+
+        parse_fen("4k3/8/8/8/8/8/4K3/R6R w - - 0 1", &char_pieces);
+        assert_eq!(parse_san("Rd1"), None);
+        assert_eq!(parse_san("Rhd1"), parse_move("h1d1").ok());
+    }
+
+    #[test]
+    fn promotion_check_and_mate() {
+        let (_lock, char_pieces) = init_test();
+
+        assert_eq!(san_of("7k/P7/8/8/8/8/8/K7 w - - 0 1", "a7a8q", &char_pieces), "a8=Q+");
+        assert_eq!(san_of("7k/P7/8/8/8/8/8/K7 w - - 0 1", "a7a8n", &char_pieces), "a8=N");
+        assert_eq!(san_of("1r5k/P7/8/8/8/8/8/K7 w - - 0 1", "a7b8r", &char_pieces), "axb8=R+");
+        assert_eq!(san_of("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4", "h5f7", &char_pieces), "Qxf7#");
+
+        parse_fen("7k/P7/8/8/8/8/8/K7 w - - 0 1", &char_pieces);
+        assert_eq!(parse_san("a8=Q+"), parse_move("a7a8q").ok());
+        assert_eq!(parse_san("a8Q"), parse_move("a7a8q").ok());
+        assert_eq!(parse_san("a8"), None);
+    }
+
+    #[test]
+    fn pgn_round_trip() {
+        let (_lock, char_pieces) = init_test();
+
+        let text = "[Event \"test\"]\n[Result \"1-0\"]\n\n1. e4 e5 2. Nf3 {main} (2. f4 exf4 $6) 2... Nc6 3. Bc4 Bc5 4. 0-0 Nf6 5. d3 0-0 1-0\n";
+
+        let games = read_games(text);
+        assert_eq!(games.len(), 1);
+
+        let sans = games[0].moves.iter().map(|mv| mv.san.as_str()).collect::<Vec<&str>>();
+        assert_eq!(sans, ["e4", "e5", "Nf3", "Nc6", "Bc4", "Bc5", "0-0", "Nf6", "d3", "0-0"]);
+        assert_eq!(games[0].moves[2].variations.len(), 1);
+
+        // written back & read again the game doesn't change
+        let written = write_game(&games[0]);
+        let again = read_games(&written);
+
+        assert_eq!(write_game(&again[0]), written);
+        assert_eq!(again[0].result, "1-0");
+
+        // every move is legal in order
+        parse_fen(START_POSTITION, &char_pieces);
+
+        for pgn_move in &again[0].moves {
+            let mv = parse_san(&pgn_move.san).unwrap();
+            make_move(mv, MOVE_TYPE::all_moves);
+        }
+    }
+}
@@ -0,0 +1,185 @@
+/*
+    Sequential probability ratio test
+
+    Decides between H0: elo = elo0 & H1: elo = elo1 (logistic Elo) with
+    false positive rate alpha & false negative rate beta. The log
+    likelihood ratio uses the normal approximation of the generalised
+    SPRT on the score of each outcome:
+
+        LLR = N * (s1 - s0) * (2 * s - s0 - s1) / (2 * var)
+
+    where s & var are the mean & variance of the observed outcomes and
+    s0, s1 the expected scores under H0 & H1. Outcomes are either single
+    games (loss, draw, win) or game pairs played with reversed colors
+    (pentanomial: 0, 0.5, 1, 1.5 or 2 points out of 2), which removes
+    the noise of unbalanced openings.
+
+    sprt <elo0> <elo1> <alpha> <beta> <losses> <draws> <wins>
+    sprt <elo0> <elo1> <alpha> <beta> <p0> <p1> <p2> <p3> <p4>
+*/
+
+// added to every count so empty outcomes keep the variance positive
+const REGULARISATION: f64 = 1e-3;
+
+#[derive(Copy, Clone)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Decision {
+    AcceptH0,
+    AcceptH1,
+    Continue,
+}
+
+// expected score of a logistic Elo difference
+fn elo_to_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+impl Sprt {
+    pub fn is_valid(&self) -> bool {
+        self.elo0 < self.elo1 && self.alpha > 0.0 && self.alpha < 0.5 && self.beta > 0.0 && self.beta < 0.5
+    }
+
+    // LLR bounds for accepting H0 & H1
+    pub fn bounds(&self) -> (f64, f64) {
+        ((self.beta / (1.0 - self.alpha)).ln(), ((1.0 - self.beta) / self.alpha).ln())
+    }
+
+    // log likelihood ratio of outcome counts, counts[i] scores i / (counts.len() - 1)
+    pub fn llr(&self, counts: &[usize]) -> f64 {
+        let outcomes = counts.len();
+
+        if outcomes < 2 || counts.iter().sum::<usize>() == 0 {
+            return 0.0;
+        }
+
+        let counts = counts.iter().map(|&count| count as f64 + REGULARISATION).collect::<Vec<f64>>();
+        let total: f64 = counts.iter().sum();
+
+        let scores = (0..outcomes).map(|index| index as f64 / (outcomes - 1) as f64).collect::<Vec<f64>>();
+
+        let mean = counts.iter().zip(&scores).map(|(count, score)| count * score).sum::<f64>() / total;
+        let variance = counts.iter().zip(&scores).map(|(count, score)| count * (score - mean).powi(2)).sum::<f64>() / total;
+
+        if variance <= 0.0 {
+            return 0.0;
+        }
+
+        let (s0, s1) = (elo_to_score(self.elo0), elo_to_score(self.elo1));
+
+        total * (s1 - s0) * (2.0 * mean - s0 - s1) / (2.0 * variance)
+    }
+
+    pub fn decide(&self, llr: f64) -> Decision {
+        let (lower, upper) = self.bounds();
+
+        if llr >= upper {
+            Decision::AcceptH1
+        }else if llr <= lower {
+            Decision::AcceptH0
+        }else {
+            Decision::Continue
+        }
+    }
+
+    // one line report of the test state
+    pub fn report(&self, counts: &[usize]) -> String {
+        let llr = self.llr(counts);
+        let (lower, upper) = self.bounds();
+
+        let decision = match self.decide(llr) {
+            Decision::AcceptH1 => "H1 accepted",
+            Decision::AcceptH0 => "H0 accepted",
+            Decision::Continue => "continue",
+        };
+
+        format!("SPRT: llr {:.2} ({:.2}, {:.2}) [{:.2}, {:.2}] {}", llr, lower, upper, self.elo0, self.elo1, decision)
+    }
+}
+
+// run the sprt command on given counts
+pub fn run(args: &[&str]) {
+    let values = args.iter().map(|arg| arg.parse::<f64>()).collect::<Result<Vec<f64>, _>>();
+
+    let values = match values {
+        Ok(val) if val.len() == 7 || val.len() == 9 => val,
+        _ => {
+            println!("info string usage: sprt <elo0> <elo1> <alpha> <beta> <losses> <draws> <wins> | <p0> <p1> <p2> <p3> <p4>");
+            return;
+        }
+    };
+
+    let sprt = Sprt { elo0: values[0], elo1: values[1], alpha: values[2], beta: values[3] };
+
+    if !sprt.is_valid() {
+        println!("info string sprt needs elo0 < elo1 and 0 < alpha, beta < 0.5");
+        return;
+    }
+
+    let counts = values[4..].iter().map(|&count| count.max(0.0) as usize).collect::<Vec<usize>>();
+
+    println!("{}", sprt.report(&counts));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // reference values computed from the formula without regularisation
+    fn assert_llr(test: &Sprt, counts: &[usize], expected: f64) {
+        let llr = test.llr(counts);
+
+        assert!((llr - expected).abs() < 0.01, "llr of {:?} is {}, expected {}", counts, llr, expected);
+    }
+
+    #[test]
+    fn bounds() {
+        let test = Sprt { elo0: 0.0, elo1: 5.0, alpha: 0.05, beta: 0.05 };
+        let (lower, upper) = test.bounds();
+
+        assert!((lower + 2.944).abs() < 0.001);
+        assert!((upper - 2.944).abs() < 0.001);
+
+        let test = Sprt { elo0: 0.0, elo1: 5.0, alpha: 0.05, beta: 0.1 };
+        let (lower, upper) = test.bounds();
+
+        assert!((lower - (0.1f64 / 0.95).ln()).abs() < 1e-9);
+        assert!((upper - (0.9f64 / 0.05).ln()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn wdl_llr() {
+        let test = Sprt { elo0: 0.0, elo1: 5.0, alpha: 0.05, beta: 0.05 };
+
+        // losses, draws & wins
+        assert_llr(&test, &[1000, 2000, 1100], 1.983);
+        assert_llr(&test, &[1200, 2000, 1000], -6.352);
+        assert_eq!(test.llr(&[0, 0, 0]), 0.0);
+
+        assert_eq!(test.decide(test.llr(&[1000, 2000, 1100])), Decision::Continue);
+        assert_eq!(test.decide(test.llr(&[1200, 2000, 1000])), Decision::AcceptH0);
+    }
+
+    #[test]
+    fn pentanomial_llr() {
+        let test = Sprt { elo0: 0.0, elo1: 5.0, alpha: 0.05, beta: 0.05 };
+
+        assert_llr(&test, &[100, 1000, 2600, 1100, 200], 9.999);
+        assert_eq!(test.decide(test.llr(&[100, 1000, 2600, 1100, 200])), Decision::AcceptH1);
+
+        // mirrored results against mirrored hypotheses
+        let test = Sprt { elo0: -5.0, elo1: 0.0, alpha: 0.05, beta: 0.05 };
+
+        assert_llr(&test, &[200, 1100, 2600, 1000, 100], -9.999);
+
+        let test = Sprt { elo0: 0.0, elo1: 10.0, alpha: 0.05, beta: 0.05 };
+
+        assert_llr(&test, &[10, 200, 500, 250, 40], 7.328);
+    }
+}
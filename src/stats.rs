@@ -0,0 +1,83 @@
+/*
+    Search statistics (stats feature)
+
+    Counters of the last search, printed as info strings before the best
+    move & by the "stats" command:
+        tt          probes, entries of the position found & cutoffs
+        cutoffs     beta cutoffs & how many came from the first move
+        null move   searches & cutoffs
+        lmr         reduced searches & the ones searched again
+        quiescence  share of the nodes searched by quiescence
+        aspiration  narrow window searches failing low or high
+
+    Without the feature the stat! macro is empty so the search pays
+    nothing for them.
+*/
+
+use super::*;
+
+#[derive(Copy, Clone)]
+pub struct SearchStats {
+    pub tt_probes: u64,
+    pub tt_hits: u64,
+    pub tt_cutoffs: u64,
+    pub beta_cutoffs: u64,
+    pub first_move_cutoffs: u64,
+    pub null_move_searches: u64,
+    pub null_move_cutoffs: u64,
+    pub lmr_searches: u64,
+    pub lmr_researches: u64,
+    pub quiescence_nodes: u64,
+    pub aspiration_searches: u64,
+    pub aspiration_fails_low: u64,
+    pub aspiration_fails_high: u64,
+}
+
+impl SearchStats {
+    const fn new() -> SearchStats {
+        SearchStats {
+            tt_probes: 0,
+            tt_hits: 0,
+            tt_cutoffs: 0,
+            beta_cutoffs: 0,
+            first_move_cutoffs: 0,
+            null_move_searches: 0,
+            null_move_cutoffs: 0,
+            lmr_searches: 0,
+            lmr_researches: 0,
+            quiescence_nodes: 0,
+            aspiration_searches: 0,
+            aspiration_fails_low: 0,
+            aspiration_fails_high: 0,
+        }
+    }
+}
+
+pub static mut STATS: SearchStats = SearchStats::new();
+
+pub fn clear() {
+    unsafe {
+        STATS = SearchStats::new();
+    }
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+    if total == 0 { 0.0 } else { part as f64 * 100.0 / total as f64 }
+}
+
+pub fn print() {
+    let (stats, nodes) = unsafe { (STATS, NODES as u64) };
+
+    println!("info string tt hits {:.1}% ({} of {} probes) cutoffs {}",
+        percent(stats.tt_hits, stats.tt_probes), stats.tt_hits, stats.tt_probes, stats.tt_cutoffs);
+    println!("info string beta cutoffs {} first move {:.1}%",
+        stats.beta_cutoffs, percent(stats.first_move_cutoffs, stats.beta_cutoffs));
+    println!("info string null move cutoffs {:.1}% ({} of {})",
+        percent(stats.null_move_cutoffs, stats.null_move_searches), stats.null_move_cutoffs, stats.null_move_searches);
+    println!("info string lmr re-searches {:.1}% ({} of {})",
+        percent(stats.lmr_researches, stats.lmr_searches), stats.lmr_researches, stats.lmr_searches);
+    println!("info string quiescence nodes {:.1}% ({} of {})",
+        percent(stats.quiescence_nodes, nodes), stats.quiescence_nodes, nodes);
+    println!("info string aspiration fails low {} high {} of {} searches",
+        stats.aspiration_fails_low, stats.aspiration_fails_high, stats.aspiration_searches);
+}
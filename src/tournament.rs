@@ -0,0 +1,779 @@
+/*
+    Engine vs engine matches
+
+    match <engine1> <engine2> [games N] [openings FILE] [tc BASE+INC]
+          [movetime MS] [nodes N] [depth D] [pgn FILE]
+          [sprt ELO0 ELO1] [alpha A] [beta B]
+
+    Both engines are UCI processes (two cheng builds work too). Openings
+    are read from a file of FENs/EPDs (one per line) or from the main
+    lines of a PGN file, and every opening is played twice with colors
+    reversed. Games are played on our own board, which adjudicates
+    mates, stalemates, repetitions, the fifty move rule, insufficient
+    material & resignations (both engines agree on a decisive score).
+
+    Time controls: tc in seconds with an increment (10+0.1), a fixed
+    time per move, a node or a depth limit. An engine losing on time,
+    playing an illegal move, crashing (disconnect) or going silent under
+    a node or depth limit (stalled connection) loses the game. Crashes &
+    stalls are counted apart from time forfeits in the match summary.
+
+    With sprt the match runs an SPRT on the pentanomial results of game
+    pairs & stops as soon as H0 or H1 is accepted. Without games it plays
+    up to 100000 games (otherwise 2).
+*/
+
+use super::*;
+
+use std::io::{BufRead, BufReader};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+// score (from the engine's side) both engines must agree on to end the game
+const RESIGN_SCORE: i32 = 1000;
+
+// consecutive moves of each engine with a resignation score
+const RESIGN_MOVES: usize = 3;
+
+// games reaching this length are drawn
+const MAX_GAME_PLIES: usize = 600;
+
+// time allowed above the clock before a loss on time (ms)
+const TIME_MARGIN: u64 = 100;
+
+// time an engine searching by nodes or depth may stay silent before the game is abandoned (ms)
+const STALL_TIMEOUT: u64 = 60000;
+
+// game limit of an sprt without a games option
+pub const SPRT_GAMES: usize = 100000;
+
+// reported mate scores
+const MATE_REPORT_SCORE: i32 = 100000;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum TimeControl {
+    // base & increment in ms
+    Clock(u64, u64),
+    MoveTime(u64),
+    Nodes(u64),
+    Depth(u64),
+}
+
+pub struct MatchSettings {
+    pub engines: [String; 2],
+    pub games: usize,
+    pub openings: Option<String>,
+    pub time_control: TimeControl,
+    pub pgn: Option<String>,
+    pub sprt: Option<sprt::Sprt>,
+}
+
+// match settings from the arguments of the match command
+pub fn parse_settings(args: &[&str]) -> Result<MatchSettings, String> {
+    if args.len() < 2 {
+        return Err(String::from("usage: match <engine1> <engine2> [games N] [openings FILE] [tc BASE+INC] [movetime MS] [nodes N] [depth D] [pgn FILE] [sprt ELO0 ELO1] [alpha A] [beta B]"));
+    }
+
+    let option = |name: &str| args.iter().position(|arg| *arg == name).and_then(|index| args.get(index + 1)).map(|val| val.to_string());
+    let number = |name: &str| option(name).and_then(|val| val.parse::<u64>().ok());
+
+    // tc in seconds, e.g. 10+0.1
+    let clock = option("tc").and_then(|tc| {
+        let (base, increment) = tc.split_once('+').unwrap_or((tc.as_str(), "0"));
+        Some(((base.parse::<f64>().ok()? * 1000.0) as u64, (increment.parse::<f64>().ok()? * 1000.0) as u64))
+    });
+
+    let time_control = if let Some((base, increment)) = clock {
+        TimeControl::Clock(base, increment)
+    }else if let Some(movetime) = number("movetime") {
+        TimeControl::MoveTime(movetime)
+    }else if let Some(nodes) = number("nodes") {
+        TimeControl::Nodes(nodes)
+    }else {
+        TimeControl::Depth(number("depth").unwrap_or(6))
+    };
+
+    let float = |name: &str| option(name).and_then(|val| val.parse::<f64>().ok());
+
+    // sprt ELO0 ELO1
+    let sprt = args.iter().position(|arg| *arg == "sprt").and_then(|index| {
+        Some(sprt::Sprt {
+            elo0: args.get(index + 1)?.parse::<f64>().ok()?,
+            elo1: args.get(index + 2)?.parse::<f64>().ok()?,
+            alpha: float("alpha").unwrap_or(0.05),
+            beta: float("beta").unwrap_or(0.05),
+        })
+    });
+
+    if sprt.as_ref().is_some_and(|test| !test.is_valid()) {
+        return Err(String::from("sprt needs elo0 < elo1 and 0 < alpha, beta < 0.5"));
+    }
+
+    // an sprt runs until it decides unless the games are limited
+    let games = match number("games") {
+        Some(games) => games as usize,
+        None if sprt.is_some() => SPRT_GAMES,
+        None => 2,
+    };
+
+    Ok(MatchSettings {
+        engines: [args[0].to_string(), args[1].to_string()],
+        games,
+        openings: option("openings"),
+        time_control,
+        pgn: option("pgn"),
+        sprt,
+    })
+}
+
+// game outcome from white's point of view
+#[derive(Copy, Clone, PartialEq)]
+enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+impl GameResult {
+    fn as_pgn(self) -> &'static str {
+        match self {
+            GameResult::WhiteWins => "1-0",
+            GameResult::BlackWins => "0-1",
+            GameResult::Draw => "1/2-1/2",
+        }
+    }
+
+    // the side to move lost
+    fn loss_for(side: usize) -> GameResult {
+        if side == PieceColor::WHITE as usize { GameResult::BlackWins } else { GameResult::WhiteWins }
+    }
+}
+
+struct Engine {
+    name: String,
+    process: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+}
+
+// bestmove & last reported score of a search
+struct SearchReport {
+    best_move: String,
+    score: Option<i32>,
+    depth: u64,
+}
+
+impl Engine {
+    fn start(path: &str) -> Result<Engine, String> {
+        let mut process = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("cannot start {}: {}", path, e))?;
+
+        let stdin = process.stdin.take().ok_or(format!("{}: no stdin", path))?;
+        let stdout = process.stdout.take().ok_or(format!("{}: no stdout", path))?;
+
+        // read output on its own thread so waiting can time out
+        let (sender, lines) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut engine = Engine { name: path.to_string(), process, stdin, lines };
+
+        engine.send("uci");
+
+        loop {
+            let line = engine.read_line(Some(10000)).map_err(|_| format!("{}: no uciok", path))?;
+
+            if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = name.trim().to_string();
+            }
+
+            if line.trim() == "uciok" {
+                break;
+            }
+        }
+
+        Ok(engine)
+    }
+
+    fn send(&mut self, command: &str) {
+        let _ = writeln!(self.stdin, "{}", command);
+        let _ = self.stdin.flush();
+    }
+
+    // next output line (Err on timeout or when the engine is gone)
+    fn read_line(&mut self, timeout: Option<u64>) -> Result<String, RecvTimeoutError> {
+        match timeout {
+            Some(ms) => self.lines.recv_timeout(Duration::from_millis(ms)),
+            None => self.lines.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        }
+    }
+
+    fn new_game(&mut self) -> bool {
+        self.send("ucinewgame");
+        self.send("isready");
+
+        loop {
+            match self.read_line(Some(10000)) {
+                Ok(line) if line.trim() == "readyok" => return true,
+                Ok(_) => {},
+                Err(_) => return false,
+            }
+        }
+    }
+
+    // search until bestmove (Err on timeout or when the engine is gone)
+    fn search(&mut self, go: &str, timeout: Option<u64>) -> Result<SearchReport, RecvTimeoutError> {
+        self.send(go);
+
+        let start = get_time_ms();
+
+        let mut report = SearchReport { best_move: String::new(), score: None, depth: 0 };
+
+        loop {
+            let remaining = timeout.map(|ms| (start + ms).saturating_sub(get_time_ms()));
+
+            let line = self.read_line(remaining)?;
+            let words = line.split_whitespace().collect::<Vec<&str>>();
+
+            match words.first() {
+                Some(&"info") => {
+                    let value = |name: &str| words.iter().position(|word| *word == name).and_then(|index| words.get(index + 1)).and_then(|val| val.parse::<i32>().ok());
+
+                    if let Some(depth) = value("depth") {
+                        report.depth = depth.max(0) as u64;
+                    }
+
+                    if let Some(score) = value("cp") {
+                        report.score = Some(score);
+                    }else if let Some(mate) = value("mate") {
+                        report.score = Some(if mate > 0 { MATE_REPORT_SCORE - mate } else { -MATE_REPORT_SCORE - mate });
+                    }
+                },
+                Some(&"bestmove") => {
+                    // a missing move is scored as an illegal move
+                    report.best_move = words.get(1).unwrap_or(&"").to_string();
+                    return Ok(report);
+                },
+                _ => {},
+            }
+        }
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        self.send("quit");
+
+        // give the engine a moment to exit on its own
+        for _ in 0..20 {
+            if let Ok(Some(_)) = self.process.try_wait() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+// opening positions as FEN & the moves leading out of them
+fn read_openings(path: &str, char_pieces: &HashMap<char, u32>) -> Result<Vec<(String, Vec<u64>)>, String> {
+    let text = std::fs::read(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    let text = String::from_utf8_lossy(&text).to_string();
+
+    let mut openings = Vec::new();
+
+    // openings with a FEN that can't be set up are skipped
+    let mut invalid_openings = 0;
+
+    if path.ends_with(".pgn") {
+        for game in pgn::read_games(&text) {
+            let fen = game.tag("FEN").unwrap_or(START_POSTITION).trim().to_string();
+
+            if try_parse_fen(&fen, char_pieces).is_err() {
+                invalid_openings += 1;
+                continue;
+            }
+
+            let mut moves = Vec::new();
+
+            for pgn_move in &game.moves {
+                match pgn::parse_san(&pgn_move.san) {
+                    Some(mv) => {
+                        moves.push(mv);
+                        make_move(mv, MOVE_TYPE::all_moves);
+                    },
+                    None => break,
+                }
+            }
+
+            openings.push((fen, moves));
+        }
+    }else {
+        for line in text.lines() {
+            // EPD lines keep the first four fields
+            let fields = line.split_whitespace().take_while(|field| !field.ends_with(';')).collect::<Vec<&str>>();
+
+            if fields.len() < 4 {
+                continue;
+            }
+
+            let fen = match fields.get(4..6) {
+                Some(counters) if counters.iter().all(|field| field.parse::<u32>().is_ok()) => fields[..6].join(" "),
+                _ => format!("{} 0 1", fields[..4].join(" ")),
+            };
+
+            if try_parse_fen(&fen, char_pieces).is_err() {
+                invalid_openings += 1;
+                continue;
+            }
+
+            openings.push((fen, Vec::new()));
+        }
+    }
+
+    if invalid_openings > 0 {
+        println!("info string {}: skipped {} openings with an invalid FEN", path, invalid_openings);
+    }
+
+    if openings.is_empty() {
+        return Err(format!("{}: no openings found", path));
+    }
+
+    Ok(openings)
+}
+
+// today's date for the PGN Date tag
+fn pgn_date() -> String {
+    let days = (SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0) / 86400) as i64;
+
+    // civil date from days since 1970-01-01
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+// play a move on the board & add it to the game
+fn record_move(mv: u64, comment: Option<String>, game: &mut pgn::PgnGame, uci_moves: &mut Vec<String>, fifty_move_counter: &mut usize) {
+    let mut pgn_move = pgn::PgnMove::new(&pgn::move_to_san(mv));
+    pgn_move.comment_after = comment;
+    game.moves.push(pgn_move);
+
+    uci_moves.push(get_uci_move(mv));
+
+    let piece = get_move_piece!(mv);
+
+    if get_move_capture!(mv) != 0 || piece == Piece::P as u64 || piece == Piece::p as u64 {
+        *fifty_move_counter = 0;
+    }else {
+        *fifty_move_counter += 1;
+    }
+
+    make_game_move(mv);
+}
+
+// play one game, engines[0] has white
+fn play_game(engines: &mut [Engine; 2], fen: &str, opening_moves: &[u64], time_control: TimeControl, round: usize, char_pieces: &HashMap<char, u32>) -> Result<(GameResult, pgn::PgnGame), String> {
+    let mut game = pgn::PgnGame::new();
+
+    for (name, value) in [
+        ("Event", String::from("cheng match")),
+        ("Site", String::from("?")),
+        ("Date", pgn_date()),
+        ("Round", round.to_string()),
+        ("White", engines[0].name.clone()),
+        ("Black", engines[1].name.clone()),
+    ] {
+        game.tags.push((name.to_string(), value));
+    }
+
+    if fen.trim() != START_POSTITION.trim() {
+        game.tags.push((String::from("SetUp"), String::from("1")));
+        game.tags.push((String::from("FEN"), fen.to_string()));
+    }
+
+    try_parse_fen(fen, char_pieces)?;
+
+    let mut uci_moves: Vec<String> = Vec::new();
+
+    // plies since the last capture or pawn move
+    let mut fifty_move_counter = 0;
+
+    // moves in a row each engine reported a lost (negative) or won (positive) score
+    let mut resign_counts = [0i32; 2];
+
+    // remaining clock time of both sides
+    let mut clocks = match time_control {
+        TimeControl::Clock(base, _) => [base as i64; 2],
+        _ => [0; 2],
+    };
+
+    for &mv in opening_moves {
+        record_move(mv, None, &mut game, &mut uci_moves, &mut fifty_move_counter);
+    }
+
+    // opening moves are part of the starting position of both engines
+    if !opening_moves.is_empty() {
+        if let Some(first) = game.moves.first_mut() {
+            first.comment_before = Some(String::from("book"));
+        }
+    }
+
+    for (side, engine) in engines.iter_mut().enumerate() {
+        if !engine.new_game() {
+            return Ok(finish_game(game, GameResult::loss_for(side), "abandoned"));
+        }
+    }
+
+    let (result, termination) = loop {
+        let side = unsafe { SIDE as usize };
+
+        // game over on the board
+        if generate_moves().is_empty() {
+            break if is_in_check() { (GameResult::loss_for(side), "checkmate") } else { (GameResult::Draw, "stalemate") };
+        }
+
+        if is_threefold_repetition() {
+            break (GameResult::Draw, "threefold repetition");
+        }
+
+        if fifty_move_counter >= 100 {
+            break (GameResult::Draw, "fifty move rule");
+        }
+
+        if endgame::is_insufficient_material() {
+            break (GameResult::Draw, "insufficient material");
+        }
+
+        if uci_moves.len() >= MAX_GAME_PLIES {
+            break (GameResult::Draw, "adjudication");
+        }
+
+        let position = if uci_moves.is_empty() {
+            format!("position fen {}", fen.trim())
+        }else {
+            format!("position fen {} moves {}", fen.trim(), uci_moves.join(" "))
+        };
+
+        let (go, timeout) = match time_control {
+            TimeControl::Clock(_, increment) => (
+                format!("go wtime {} btime {} winc {} binc {}", clocks[0].max(0), clocks[1].max(0), increment, increment),
+                Some(clocks[side].max(0) as u64 + TIME_MARGIN),
+            ),
+            TimeControl::MoveTime(ms) => (format!("go movetime {}", ms), Some(ms + 10 * TIME_MARGIN)),
+            TimeControl::Nodes(nodes) => (format!("go nodes {}", nodes), None),
+            TimeControl::Depth(depth) => (format!("go depth {}", depth), None),
+        };
+
+        // only a clock or a move time can be lost on time, other searches stall
+        let on_clock = timeout.is_some();
+
+        let engine = &mut engines[side];
+        engine.send(&position);
+
+        let start = get_time_ms();
+
+        let report = match engine.search(&go, timeout.or(Some(STALL_TIMEOUT))) {
+            Ok(val) => val,
+            Err(RecvTimeoutError::Timeout) if on_clock => break (GameResult::loss_for(side), "time forfeit"),
+            Err(RecvTimeoutError::Timeout) => break (GameResult::loss_for(side), "stalled connection"),
+            Err(RecvTimeoutError::Disconnected) => break (GameResult::loss_for(side), "disconnect"),
+        };
+
+        let elapsed = get_time_ms() - start;
+
+        if let TimeControl::Clock(_, increment) = time_control {
+            clocks[side] -= elapsed as i64;
+
+            if clocks[side] < -(TIME_MARGIN as i64) {
+                break (GameResult::loss_for(side), "time forfeit");
+            }
+
+            clocks[side] += increment as i64;
+        }
+
+        let mv = match parse_move(&report.best_move) {
+            Ok(val) => val,
+            Err(_) => break (GameResult::loss_for(side), "illegal move"),
+        };
+
+        // resignation needs both engines to agree
+        resign_counts[side] = match report.score {
+            Some(score) if score <= -RESIGN_SCORE => resign_counts[side].min(0) - 1,
+            Some(score) if score >= RESIGN_SCORE => resign_counts[side].max(0) + 1,
+            _ => 0,
+        };
+
+        let comment = report.score.map(|score| {
+            if score.abs() > MATE_REPORT_SCORE / 2 {
+                format!("{}M{}/{} {}ms", if score > 0 { "+" } else { "-" }, MATE_REPORT_SCORE - score.abs(), report.depth, elapsed)
+            }else {
+                format!("{:+.2}/{} {}ms", score as f64 / 100.0, report.depth, elapsed)
+            }
+        });
+
+        record_move(mv, comment, &mut game, &mut uci_moves, &mut fifty_move_counter);
+
+        let limit = RESIGN_MOVES as i32;
+
+        if resign_counts[side] <= -limit && resign_counts[side ^ 1] >= limit {
+            break (GameResult::loss_for(side), "adjudication");
+        }
+
+        if resign_counts[side] >= limit && resign_counts[side ^ 1] <= -limit {
+            break (GameResult::loss_for(side ^ 1), "adjudication");
+        }
+    };
+
+    Ok(finish_game(game, result, termination))
+}
+
+fn finish_game(mut game: pgn::PgnGame, result: GameResult, termination: &str) -> (GameResult, pgn::PgnGame) {
+    game.result = result.as_pgn().to_string();
+
+    // Result tag follows the roster tags
+    game.tags.insert(6.min(game.tags.len()), (String::from("Result"), game.result.clone()));
+    game.tags.push((String::from("Termination"), termination.to_string()));
+
+    if let Some(last) = game.moves.last_mut() {
+        pgn::add_comment(&mut last.comment_after, termination.to_string());
+    }
+
+    (result, game)
+}
+
+// Elo difference of a score fraction
+fn elo_from_score(score: f64) -> f64 {
+    let score = score.clamp(1e-6, 1.0 - 1e-6);
+
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+// Elo estimate & 95% error margin from wins, draws & losses
+pub fn elo_estimate(wins: usize, draws: usize, losses: usize) -> (f64, f64) {
+    let games = (wins + draws + losses) as f64;
+
+    if games == 0.0 {
+        return (0.0, 0.0);
+    }
+
+    let score = (wins as f64 + draws as f64 / 2.0) / games;
+
+    // variance of a single game result
+    let variance = (wins as f64 * (1.0 - score).powi(2) + draws as f64 * (0.5 - score).powi(2) + losses as f64 * score.powi(2)) / games;
+
+    let margin = 1.959964 * (variance / games).sqrt();
+
+    let elo = elo_from_score(score);
+    let error = (elo_from_score(score + margin) - elo_from_score(score - margin)) / 2.0;
+
+    (elo, error)
+}
+
+// run a match, results are given for the first engine
+pub fn run_match(settings: &MatchSettings, char_pieces: &HashMap<char, u32>) {
+    let openings = match &settings.openings {
+        Some(path) => match read_openings(path, char_pieces) {
+            Ok(val) => val,
+            Err(e) => {
+                println!("info string {}", e);
+                return;
+            }
+        },
+        None => vec![(START_POSTITION.trim().to_string(), Vec::new())],
+    };
+
+    let mut engines = Vec::new();
+
+    for path in &settings.engines {
+        match Engine::start(path) {
+            Ok(val) => engines.push(val),
+            Err(e) => {
+                println!("info string {}", e);
+                return;
+            }
+        }
+    }
+
+    let mut engines: [Engine; 2] = match engines.try_into() {
+        Ok(val) => val,
+        Err(_) => return,
+    };
+
+    let names = [engines[0].name.clone(), engines[1].name.clone()];
+
+    let mut pgn_file = match &settings.pgn {
+        Some(path) => match std::fs::File::create(path) {
+            Ok(val) => Some(val),
+            Err(e) => {
+                println!("info string cannot create {}: {}", path, e);
+                return;
+            }
+        },
+        None => None,
+    };
+
+    // wins, draws & losses of the first engine
+    let (mut wins, mut draws, mut losses) = (0, 0, 0);
+
+    // game pairs by points of the first engine (0, 0.5, ... 2)
+    let mut pentanomial = [0; 5];
+
+    // half points of the first engine in the current pair
+    let mut pair_points = 0;
+
+    // games lost on time & by an engine crashing, stalling or not getting ready
+    let (mut time_forfeits, mut crashes) = (0, 0);
+
+    for game_index in 0..settings.games {
+        let (fen, opening_moves) = &openings[(game_index / 2) % openings.len()];
+
+        // colors alternate so every opening is played from both sides
+        let first_is_white = game_index % 2 == 0;
+
+        if !first_is_white {
+            engines.swap(0, 1);
+        }
+
+        let played = play_game(&mut engines, fen, opening_moves, settings.time_control, game_index + 1, char_pieces);
+
+        if !first_is_white {
+            engines.swap(0, 1);
+        }
+
+        let (result, game) = match played {
+            Ok(val) => val,
+            Err(e) => {
+                println!("info string {}", e);
+                break;
+            }
+        };
+
+        pair_points += match (result, first_is_white) {
+            (GameResult::Draw, _) => {
+                draws += 1;
+                1
+            },
+            (GameResult::WhiteWins, true) | (GameResult::BlackWins, false) => {
+                wins += 1;
+                2
+            },
+            _ => {
+                losses += 1;
+                0
+            },
+        };
+
+        match game.tags.iter().find(|(name, _)| name == "Termination").map(|(_, value)| value.as_str()) {
+            Some("time forfeit") => time_forfeits += 1,
+            Some("disconnect") | Some("stalled connection") | Some("abandoned") => crashes += 1,
+            _ => {},
+        }
+
+        if let Some(file) = pgn_file.as_mut() {
+            if let Err(e) = file.write_all(pgn::write_game(&game).as_bytes()) {
+                println!("info string cannot write pgn: {}", e);
+            }
+        }
+
+        let games = wins + draws + losses;
+
+        println!("Score of {} vs {}: {} - {} - {} [{:.3}] {}",
+            names[0], names[1], wins, losses, draws,
+            (wins as f64 + draws as f64 / 2.0) / games as f64, games,
+        );
+
+        // both colors of the opening are played
+        if !first_is_white {
+            pentanomial[pair_points] += 1;
+            pair_points = 0;
+
+            if let Some(test) = &settings.sprt {
+                println!("{}", test.report(&pentanomial));
+
+                let llr = test.llr(&pentanomial);
+
+                if test.decide(llr) != sprt::Decision::Continue {
+                    break;
+                }
+            }
+        }
+    }
+
+    println!("Pentanomial: [{}]", pentanomial.iter().map(|count| count.to_string()).collect::<Vec<String>>().join(", "));
+
+    println!("Time forfeits: {} crashes: {}", time_forfeits, crashes);
+
+    let (elo, error) = elo_estimate(wins, draws, losses);
+
+    println!("Elo difference: {:.1} +/- {:.1}", elo, error);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_openings_are_skipped() {
+        let (_lock, char_pieces) = init_test();
+
+        let epd_path = std::env::temp_dir().join("cheng_openings_test.epd");
+        let pgn_path = std::env::temp_dir().join("cheng_openings_test.pgn");
+
+        std::fs::write(&epd_path, "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1\nnot/a/fen w - -\n4k3/8/8/8/8/8/8/8 w - - bm Kd7;\n").unwrap();
+        std::fs::write(&pgn_path, "[FEN \"8/8/8 w - - 0 1\"]\n\n1. e4 *\n\n1. d4 d5 *\n").unwrap();
+
+        let epd_openings = read_openings(epd_path.to_str().unwrap(), &char_pieces);
+        let pgn_openings = read_openings(pgn_path.to_str().unwrap(), &char_pieces);
+
+        std::fs::remove_file(epd_path).unwrap();
+        std::fs::remove_file(pgn_path).unwrap();
+
+        let epd_openings = epd_openings.unwrap();
+        assert_eq!(epd_openings.len(), 1);
+        assert_eq!(epd_openings[0].0, "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1");
+
+        let pgn_openings = pgn_openings.unwrap();
+        assert_eq!(pgn_openings.len(), 1);
+        assert_eq!(pgn_openings[0].1.iter().map(|mv| get_uci_move(*mv)).collect::<Vec<String>>(), ["d2d4", "d7d5"]);
+    }
+
+    #[test]
+    fn match_settings() {
+        let settings = parse_settings(&["a", "b", "tc", "10+0.1", "sprt", "0", "5", "pgn", "games.pgn"]).unwrap();
+
+        assert_eq!(settings.engines, [String::from("a"), String::from("b")]);
+        assert!(settings.time_control == TimeControl::Clock(10000, 100));
+        assert_eq!(settings.games, SPRT_GAMES);
+        assert_eq!(settings.pgn.as_deref(), Some("games.pgn"));
+
+        let settings = parse_settings(&["a", "b", "games", "10", "nodes", "5000"]).unwrap();
+
+        assert!(settings.time_control == TimeControl::Nodes(5000));
+        assert_eq!(settings.games, 10);
+        assert!(settings.sprt.is_none());
+
+        assert!(parse_settings(&["a"]).is_err());
+        assert!(parse_settings(&["a", "b", "sprt", "5", "0"]).is_err());
+    }
+}